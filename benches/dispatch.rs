@@ -0,0 +1,44 @@
+//! Compares the throughput of the single-block path and the four-block path of the ChaCha20
+//! keystream generation at several buffer sizes, to pick the size from which the four-block path
+//! should be dispatched to.
+//!
+//! The four-block path runs on the SIMD backends when the CPU supports one, and on the scalar
+//! block function otherwise.
+//!
+//! Run with `cargo bench --features bench --bench dispatch`.
+
+use chacha20poly1305::chacha20::Block;
+use chacha20poly1305::ChaCha20;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+const BUFFER_SIZES: [usize; 3] = [256, 4 * 1024, 64 * 1024];
+
+fn dispatch(c: &mut Criterion) {
+    for buffer_size in BUFFER_SIZES {
+        let mut group = c.benchmark_group(format!("dispatch/{buffer_size} B"));
+        group.throughput(Throughput::Bytes(buffer_size as u64));
+
+        for (name, blocks_per_call) in [("single block", 1), ("four blocks", 4)] {
+            let mut cipher = ChaCha20::new(&[0x42; 32], &[0x24; 12]);
+            let mut buffer: Vec<Block> = vec![[0; 64]; buffer_size / 64];
+
+            group.bench_function(BenchmarkId::from_parameter(name), |b| {
+                b.iter(|| {
+                    // The counter would run out before the end of the measurement otherwise
+                    cipher.seek_to(0);
+
+                    for blocks in buffer.chunks_mut(blocks_per_call) {
+                        cipher.gen_blocks(blocks).unwrap();
+                    }
+                    black_box::<&[Block]>(&buffer);
+                })
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
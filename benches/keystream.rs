@@ -0,0 +1,38 @@
+//! Compares the throughput of generating the ChaCha20 keystream one block at a time and in
+//! batches of blocks.
+//!
+//! Run with `cargo bench --features bench --bench keystream`.
+
+use chacha20poly1305::chacha20::Block;
+use chacha20poly1305::ChaCha20;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+const TOTAL_BLOCKS: usize = 1024;
+
+fn keystream(c: &mut Criterion) {
+    let mut group = c.benchmark_group("keystream");
+    group.throughput(Throughput::Bytes((TOTAL_BLOCKS * 64) as u64));
+
+    for batch in [1, 4, 16, 64] {
+        let mut cipher = ChaCha20::new(&[0x42; 32], &[0x24; 12]);
+        let mut blocks: Vec<Block> = vec![[0; 64]; batch];
+
+        group.bench_function(BenchmarkId::new("blocks per call", batch), |b| {
+            b.iter(|| {
+                // The counter would run out before the end of the measurement otherwise
+                cipher.seek_to(0);
+
+                for _ in 0..TOTAL_BLOCKS / batch {
+                    cipher.gen_blocks(&mut blocks).unwrap();
+                    black_box(&blocks);
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, keystream);
+criterion_main!(benches);
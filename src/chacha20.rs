@@ -3,8 +3,17 @@
 use crate::error::Error;
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+use zeroize::Zeroize as _;
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx2;
+#[cfg(feature = "cipher")]
+mod cipher_traits;
 mod cross_arch;
+#[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+mod neon;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod ssse3;
 
 pub(crate) use cross_arch::ChaCha20Inner;
 
@@ -13,6 +22,9 @@ const NUMBER_OF_ROUND: usize = 20;
 
 const BLOCK_SIZE: usize = 64;
 
+/// Alignment in bytes required by `ChaCha20::fill_keystream_aligned`
+pub const KEYSTREAM_ALIGNMENT: usize = 16;
+
 /// Size of Key in bit
 const KEY_SIZE: usize = 256;
 
@@ -22,11 +34,38 @@ const NONCE_SIZE: usize = 96;
 /// Initialization constants
 const INIT_CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
 
-pub(crate) type Block = [u8; BLOCK_SIZE];
+/// Represents a block of the ChaCha20 keystream. It is an array of bytes with a size of 64.
+pub type Block = [u8; BLOCK_SIZE];
 /// Represents the ChaCha20 key. It is an array of bytes with a size of 32, or 256 bits.
 pub type Key = [u8; KEY_SIZE / 8];
 /// Represents the ChaCha20 key. It is an array of bytes with a size of 12, or 96 bits.
 pub type Nonce = [u8; NONCE_SIZE / 8];
+/// Represents the nonce of the original ChaCha20 variant. It is an array of bytes with a size of 8, or 64 bits.
+pub type LegacyNonce = [u8; 8];
+
+/// Derives 32 bytes from a key and a 16 bytes input with HChaCha20, as specified in the XChaCha
+/// draft (draft-irtf-cfrg-xchacha). It is a fast PRF, suitable for deriving subkeys.
+///
+/// The input takes the place of the counter and the nonce, and the output is made of the words
+/// 0 to 3 and 12 to 15 of the state after the 20 rounds, without the final state addition.
+pub fn hchacha20(key: &Key, input: &[u8; 16]) -> [u8; 32] {
+    // Unwrap here is safe because the input is split into 4 and 12 bytes
+    let counter = u32::from_le_bytes(input[..4].try_into().unwrap());
+    let nonce: Nonce = input[4..].try_into().unwrap();
+
+    let mut state = ChaCha20Inner::new_with_cnt(key, &nonce, counter).rounds();
+    let mut output = [0; 32];
+
+    for (out, word) in output
+        .chunks_exact_mut(4)
+        .zip(state[..4].iter().chain(&state[12..]))
+    {
+        out.copy_from_slice(&word.to_le_bytes());
+    }
+
+    state.zeroize();
+    output
+}
 
 #[derive(Clone)]
 /// Represents the ChaCha20 cipher state.
@@ -34,25 +73,103 @@ pub struct ChaCha20 {
     inner: ChaCha20Inner,
     buf: [u8; BLOCK_SIZE],
     available: usize,
+    /// Set by `clear`, the state is zeroed and must not produce any keystream
+    cleared: bool,
+    /// Threshold in bytes and callback set by `set_low_keystream_callback`
+    low_keystream: Option<(u64, fn())>,
 }
 
 impl ChaCha20 {
+    /// Size of the key in bytes
+    pub const KEY_SIZE: usize = core::mem::size_of::<Key>();
+    /// Size of the nonce in bytes
+    pub const NONCE_SIZE: usize = core::mem::size_of::<Nonce>();
+    /// Size of a keystream block in bytes
+    pub const BLOCK_SIZE: usize = BLOCK_SIZE;
+
     #[inline]
     /// Creates a new ChaCha20 instance with the provided key and nonce.
-    pub fn new(key: &Key, nonce: &Nonce) -> Self {
+    pub const fn new(key: &Key, nonce: &Nonce) -> Self {
         Self::new_with_cnt(key, nonce, 0)
     }
 
+    /// Creates a new ChaCha20 instance from a key and a nonce received as slices, e.g. parsed off
+    /// the wire. Returns `Error::InvalidLength` if the key is not 32 bytes long or the nonce is not
+    /// 12 bytes long.
+    pub fn new_from_slices(key: &[u8], nonce: &[u8]) -> crate::Result<Self> {
+        let mut key: Key = key.try_into().map_err(|_| Error::InvalidLength)?;
+        let nonce: Nonce = nonce.try_into().map_err(|_| Error::InvalidLength)?;
+
+        let cipher = Self::new(&key, &nonce);
+        key.zeroize();
+        Ok(cipher)
+    }
+
     #[inline]
     /// Creates a new ChaCha20 instance with the provided key, nonce, and initial counter value.
-    pub fn new_with_cnt(key: &Key, nonce: &Nonce, cnt: u32) -> Self {
+    ///
+    /// The keystream starts at the block `cnt`, e.g. 1 for the message keystream of RFC 8439
+    /// where the block 0 derives the Poly1305 key, or the block an interrupted stream stopped at
+    /// to resume it.
+    pub const fn new_with_cnt(key: &Key, nonce: &Nonce, cnt: u32) -> Self {
         Self {
             inner: ChaCha20Inner::new_with_cnt(key, nonce, cnt),
             buf: [0; BLOCK_SIZE],
             available: 0,
+            cleared: false,
+            low_keystream: None,
+        }
+    }
+
+    #[inline]
+    /// Creates a ChaCha20 instance whose 16 bytes nonce fills the state words 12 to 15, with no
+    /// separate counter, as used by some WireGuard tooling.
+    ///
+    /// **Non-standard:** this layout is not RFC 8439 and is only provided for interoperability.
+    /// The whole nonce is the block counter: it is incremented as a 128-bit little-endian integer
+    /// for every block, carrying across the 4 words, so the keystream only ends when it wraps
+    /// around from `[0xff; 16]`. `current_position` and `seek_to` only see its low word.
+    pub const fn new_nonce128(key: &Key, nonce: &[u8; 16]) -> Self {
+        Self {
+            inner: ChaCha20Inner::new_nonce128(key, nonce),
+            buf: [0; BLOCK_SIZE],
+            available: 0,
+            cleared: false,
+            low_keystream: None,
         }
     }
 
+    #[cfg(feature = "debug-internals")]
+    /// Runs the block function once and returns the working state after the 20 rounds, before the
+    /// original state is added back, along with the final serialized keystream block.
+    ///
+    /// Meant for teaching and for comparing the intermediate values with RFC 8439 section 2.3.2.
+    pub fn block_with_intermediate(key: &Key, nonce: &Nonce, counter: u32) -> ([u32; 16], Block) {
+        let mut inner = ChaCha20Inner::new_with_cnt(key, nonce, counter);
+        let mut block = [0; BLOCK_SIZE];
+
+        let working_state = inner.rounds();
+        inner.gen_block(&mut block);
+
+        (working_state, block)
+    }
+
+    #[cfg(feature = "rand_core")]
+    /// Generates a random key filling all of its 32 bytes from the provided RNG.
+    pub fn generate_key(rng: &mut impl rand_core::RngCore) -> Key {
+        let mut key: Key = Default::default();
+        rng.fill_bytes(&mut key);
+        key
+    }
+
+    #[cfg(feature = "rand_core")]
+    /// Generates a random nonce filling all of its 12 bytes from the provided RNG.
+    pub fn generate_nonce(rng: &mut impl rand_core::RngCore) -> Nonce {
+        let mut nonce: Nonce = Default::default();
+        rng.fill_bytes(&mut nonce);
+        nonce
+    }
+
     #[inline]
     /// Seeks the ChaCha20 stream to a specific position by setting the counter value.
     pub fn seek_to(&mut self, position: u32) {
@@ -60,19 +177,97 @@ impl ChaCha20 {
         self.available = 0;
     }
 
+    /// Seeks the ChaCha20 stream to a specific byte offset of the keystream, counted from the
+    /// block with counter 0.
+    ///
+    /// Returns the block counter and the offset inside that block the stream is now positioned
+    /// at, or `Error::DataTooLong` if the block counter would not fit into 32 bits.
+    pub fn seek64(&mut self, byte_offset: u64) -> crate::Result<(u32, usize)> {
+        self.check_cleared()?;
+
+        let block =
+            u32::try_from(byte_offset / BLOCK_SIZE as u64).map_err(|_| Error::DataTooLong)?;
+        let offset = (byte_offset % BLOCK_SIZE as u64) as usize;
+
+        self.seek_to(block);
+
+        if offset != 0 {
+            self.inner.gen_block(&mut self.buf);
+            self.available = BLOCK_SIZE - offset;
+        }
+
+        Ok((block, offset))
+    }
+
+    #[inline]
+    /// Seeks the ChaCha20 stream to a specific byte offset of the keystream, counted from the
+    /// block with counter 0. Useful for random-access decryption of large data.
+    pub fn seek_bytes(&mut self, offset: u64) -> crate::Result<()> {
+        self.seek64(offset).map(|_| ())
+    }
+
+    /// Reinitializes the cipher in place with a new key and nonce, with the counter back to 0, as
+    /// if it was created with `ChaCha20::new`. Also makes a cleared cipher usable again.
+    ///
+    /// The previous state and cached keystream are zeroized before being overwritten, which
+    /// suits a ratchet deriving each next key from the current keystream.
+    pub fn rekey(&mut self, key: &Key, nonce: &Nonce) {
+        self.inner.clear();
+        self.buf.zeroize();
+
+        self.inner = ChaCha20Inner::new_with_cnt(key, nonce, 0);
+        self.available = 0;
+        self.cleared = false;
+        self.low_keystream = None;
+    }
+
+    /// Sets a callback fired once, as soon as an operation leaves fewer than `threshold_bytes`
+    /// bytes of keystream before the counter runs out, so the protocol can rekey proactively.
+    ///
+    /// Setting a callback replaces the previous one, and `rekey` disarms it.
+    pub fn set_low_keystream_callback(&mut self, threshold_bytes: u64, cb: fn()) {
+        self.low_keystream = Some((threshold_bytes, cb));
+    }
+
+    /// Replaces the key while keeping the counter and the nonce, so the keystream continues from
+    /// the same position but is generated under `new_key` from now on.
+    ///
+    /// Meant for ratcheting a long stream forward: the bytes already processed stay under the old
+    /// key, while any cached keystream of the current block is regenerated under the new one.
+    pub fn rekey_preserve_position(&mut self, new_key: &Key) {
+        self.inner.set_key(new_key);
+
+        if self.available != 0 {
+            let available = self.available;
+            let block = self.inner.current_position().wrapping_sub(1);
+
+            self.inner.seek_to(block);
+            self.inner.gen_block(&mut self.buf);
+            self.available = available;
+        }
+    }
+
     #[inline]
     /// Get the current counter value
     pub fn current_position(&self) -> u32 {
         self.inner.current_position()
     }
 
+    /// Zeroes the key, the nonce and the cached keystream before the cipher is dropped.
+    ///
+    /// Every operation generating keystream afterward returns `Error::CipherCleared` instead of
+    /// XORing the data with the keystream of the zeroed state.
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.buf.zeroize();
+        self.available = 0;
+        self.cleared = true;
+    }
+
     #[inline]
     /// Performs the ChaCha20 encryption/decryption in-place on the provided data.
     pub fn perform_in_place(&mut self, data: &mut [u8]) -> crate::Result<()> {
-        let required_block = data.len().saturating_sub(self.available) / BLOCK_SIZE;
-        if self.remaining_blocks() < required_block {
-            return Err(Error::DataTooLong);
-        }
+        self.check_keystream_len(data.len())?;
 
         let full_len = data.len();
         let mut len = data.len();
@@ -84,6 +279,22 @@ impl ChaCha20 {
             len -= amount;
         }
 
+        let mut blocks = [0; 4 * BLOCK_SIZE];
+
+        while len >= blocks.len() {
+            let start_from = full_len - len;
+
+            self.inner.gen_4_blocks(&mut blocks);
+            data[start_from..start_from + blocks.len()]
+                .iter_mut()
+                .zip(&blocks)
+                .for_each(|(v, k)| *v ^= k);
+
+            len -= blocks.len();
+        }
+
+        blocks.zeroize();
+
         while len > 0 {
             let amount: usize = len.min(BLOCK_SIZE);
             let start_from = full_len - len;
@@ -97,6 +308,7 @@ impl ChaCha20 {
             len -= amount;
         }
 
+        self.notify_low_keystream();
         Ok(())
     }
 
@@ -109,9 +321,232 @@ impl ChaCha20 {
         Ok(res)
     }
 
+    /// Encrypts/decrypts as much of `input` as fits in `output`, writing the result to `output`,
+    /// and returns the number of bytes processed, `min(input.len(), output.len())`.
+    ///
+    /// The position within the current keystream block is kept, so the rest of `input` can be
+    /// processed by the next call whatever the lengths, e.g. as an output buffer drains. The bytes
+    /// of `output` past the returned count are left untouched, and nothing is written on error.
+    pub fn apply_keystream_partial(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> crate::Result<usize> {
+        let len = input.len().min(output.len());
+        self.check_keystream_len(len)?;
+
+        let output = &mut output[..len];
+        output.copy_from_slice(&input[..len]);
+        self.perform_in_place(output)?;
+        Ok(len)
+    }
+
+    #[inline]
+    /// Fills `out` with the raw keystream, continuing from the current position.
+    pub fn fill_keystream(&mut self, out: &mut [u8]) -> crate::Result<()> {
+        out.fill(0);
+        self.perform_in_place(out)
+    }
+
+    /// Fills `out` with the raw keystream as `u32` words, continuing from the current position.
+    ///
+    /// Each word is the little-endian interpretation of the next 4 keystream bytes on every
+    /// platform, so serializing the words with `u32::to_le_bytes` gives the bytes `fill_keystream`
+    /// would produce. When the position is a multiple of 4 bytes, the full blocks are copied from
+    /// the output words of the block function without being serialized.
+    pub fn fill_keystream_words(&mut self, out: &mut [u32]) -> crate::Result<()> {
+        self.check_keystream_len(out.len().saturating_mul(4))?;
+
+        if !self.available.is_multiple_of(4) {
+            let mut bytes = [0; BLOCK_SIZE];
+
+            for words in out.chunks_mut(BLOCK_SIZE / 4) {
+                let bytes = &mut bytes[..words.len() * 4];
+                self.fill_keystream(bytes)?;
+                words_from_le(words, bytes);
+            }
+
+            bytes.zeroize();
+            return Ok(());
+        }
+
+        let cached = out.len().min(self.available / 4);
+        let (head, rest) = out.split_at_mut(cached);
+        let start = BLOCK_SIZE - self.available;
+        words_from_le(head, &self.buf[start..start + cached * 4]);
+        self.available -= cached * 4;
+
+        let mut blocks = rest.chunks_exact_mut(BLOCK_SIZE / 4);
+        for block in &mut blocks {
+            block.copy_from_slice(&self.inner.gen_block_words());
+        }
+
+        let tail = blocks.into_remainder();
+        if !tail.is_empty() {
+            self.inner.gen_block(&mut self.buf);
+            self.available = BLOCK_SIZE - tail.len() * 4;
+            words_from_le(tail, &self.buf[..tail.len() * 4]);
+        }
+
+        self.notify_low_keystream();
+        Ok(())
+    }
+
+    /// Fills `out` with the raw keystream serialized as big-endian words, continuing from the
+    /// current position.
+    ///
+    /// **Non-standard:** RFC 8439 serializes the keystream words in little-endian, this output is
+    /// `fill_keystream` with every 4 bytes reversed and is not compatible with any RFC
+    /// implementation. It only exists for protocols that expect the words in big-endian, the words
+    /// are the ones `fill_keystream_words` returns.
+    ///
+    /// Returns `Error::InvalidLength` if the length of `out` is not a multiple of 4 bytes.
+    pub fn fill_keystream_be(&mut self, out: &mut [u8]) -> crate::Result<()> {
+        if !out.len().is_multiple_of(4) {
+            return Err(Error::InvalidLength);
+        }
+
+        self.check_keystream_len(out.len())?;
+
+        let mut words = [0u32; BLOCK_SIZE / 4];
+
+        for chunk in out.chunks_mut(BLOCK_SIZE) {
+            let words = &mut words[..chunk.len() / 4];
+            self.fill_keystream_words(words)?;
+
+            for (bytes, word) in chunk.chunks_exact_mut(4).zip(words.iter()) {
+                bytes.copy_from_slice(&word.to_be_bytes());
+            }
+        }
+
+        words.zeroize();
+        Ok(())
+    }
+
+    /// Returns an iterator over exactly the next `len` keystream bytes, whose `len()` reports how
+    /// many bytes are left. The cipher advances as the bytes are consumed.
+    ///
+    /// Returns `Error::DataTooLong` upfront if fewer than `len` keystream bytes remain.
+    pub fn keystream_bounded(
+        &mut self,
+        len: usize,
+    ) -> crate::Result<impl ExactSizeIterator<Item = u8> + '_> {
+        self.check_keystream_len(len)?;
+
+        Ok(BoundedKeystream {
+            cipher: self,
+            remaining: len,
+            chunk: [0; BLOCK_SIZE],
+            chunk_len: 0,
+            pos: 0,
+        })
+    }
+
+    #[inline]
+    /// Returns how many unused keystream bytes of the current block remain cached, from 0 to 63.
+    /// Processing that many bytes first aligns the next calls to block boundaries.
+    pub fn cached_keystream_len(&self) -> usize {
+        self.available
+    }
+
+    /// Fills `out` with the next blocks of the raw keystream, generating them in batches so the
+    /// per-block overhead is amortized over long keystreams.
+    ///
+    /// Continues from the current position like `fill_keystream`, and returns
+    /// `Error::DataTooLong` without generating anything if the keystream is too short.
+    pub fn gen_blocks(&mut self, out: &mut [Block]) -> crate::Result<()> {
+        self.check_cleared()?;
+
+        if self.available != 0 {
+            return self.fill_keystream(out.as_flattened_mut());
+        }
+
+        if self.remaining_blocks() < out.len() as u64 {
+            return Err(Error::DataTooLong);
+        }
+
+        self.inner.gen_blocks(out);
+        self.notify_low_keystream();
+        Ok(())
+    }
+
+    #[inline]
+    /// Returns the next `N` bytes of the raw keystream as an array, spanning blocks as needed.
+    pub fn next_bytes<const N: usize>(&mut self) -> crate::Result<[u8; N]> {
+        let mut res = [0; N];
+        self.perform_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Fills `out` with the raw keystream like `fill_keystream`, for buffers that are going to be
+    /// processed with SIMD afterward.
+    ///
+    /// `out` must start at an address aligned to `KEYSTREAM_ALIGNMENT` bytes, this is checked in
+    /// debug builds. When no keystream is cached, the full blocks are generated directly into `out`,
+    /// four at a time like `gen_blocks`.
+    pub fn fill_keystream_aligned(&mut self, out: &mut [u8]) -> crate::Result<()> {
+        debug_assert_eq!(
+            out.as_ptr().align_offset(KEYSTREAM_ALIGNMENT),
+            0,
+            "keystream buffer is not aligned to {KEYSTREAM_ALIGNMENT} bytes"
+        );
+
+        self.check_cleared()?;
+
+        if self.available != 0 {
+            return self.fill_keystream(out);
+        }
+
+        let (blocks, remainder) = out.as_chunks_mut::<BLOCK_SIZE>();
+        if self.remaining_blocks() < blocks.len() as u64 {
+            return Err(Error::DataTooLong);
+        }
+
+        self.inner.gen_blocks(blocks);
+        self.fill_keystream(remainder)
+    }
+
+    #[inline(always)]
+    fn remaining_blocks(&self) -> u64 {
+        self.inner.remaining_blocks()
+    }
+
+    /// Fires the low keystream callback if the remaining keystream fell below its threshold
+    #[inline]
+    fn notify_low_keystream(&mut self) {
+        if let Some((threshold, cb)) = self.low_keystream {
+            let remaining = self
+                .remaining_blocks()
+                .saturating_mul(BLOCK_SIZE as u64)
+                .saturating_add(self.available as u64);
+
+            if remaining < threshold {
+                self.low_keystream = None;
+                cb();
+            }
+        }
+    }
+
+    /// Checks that `len` more bytes of keystream can be generated
     #[inline(always)]
-    fn remaining_blocks(&self) -> usize {
-        (u32::MAX - self.inner.current_position()) as usize
+    fn check_keystream_len(&self, len: usize) -> crate::Result<()> {
+        self.check_cleared()?;
+
+        let required_block = len.saturating_sub(self.available).div_ceil(BLOCK_SIZE);
+        if self.remaining_blocks() < required_block as u64 {
+            return Err(Error::DataTooLong);
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn check_cleared(&self) -> crate::Result<()> {
+        if self.cleared {
+            Err(Error::CipherCleared)
+        } else {
+            Ok(())
+        }
     }
 
     #[inline]
@@ -123,8 +558,138 @@ impl ChaCha20 {
     }
 }
 
+/// Iterator returned by `ChaCha20::keystream_bounded`
+///
+/// The keystream is taken from the cipher up to the end of its current block at once, the bytes
+/// that have not been yielded are given back to the cipher when the iterator is dropped.
+struct BoundedKeystream<'a> {
+    cipher: &'a mut ChaCha20,
+    remaining: usize,
+    chunk: Block,
+    chunk_len: usize,
+    pos: usize,
+}
+
+impl Iterator for BoundedKeystream<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        if self.pos == self.chunk_len {
+            let block_left = match self.cipher.available {
+                0 => BLOCK_SIZE,
+                available => available,
+            };
+
+            self.chunk_len = self.remaining.min(block_left);
+            self.pos = 0;
+            // Unwrap here is safe because the keystream length has been checked upfront
+            self.cipher
+                .fill_keystream(&mut self.chunk[..self.chunk_len])
+                .unwrap();
+        }
+
+        let byte = self.chunk[self.pos];
+        self.pos += 1;
+        self.remaining -= 1;
+        Some(byte)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for BoundedKeystream<'_> {}
+
+impl Drop for BoundedKeystream<'_> {
+    fn drop(&mut self) {
+        // The chunk never spans two blocks, so its unused bytes are still cached by the cipher
+        self.cipher.available += self.chunk_len - self.pos;
+        self.chunk.zeroize();
+    }
+}
+
+/// Reads `bytes` as consecutive little-endian `u32`, `bytes` must be `4 * words.len()` bytes long
+#[inline(always)]
+fn words_from_le(words: &mut [u32], bytes: &[u8]) {
+    words
+        .iter_mut()
+        .zip(bytes.chunks_exact(4))
+        // Unwrap here is safe because the chunk is exactly 4 bytes long
+        .for_each(|(word, chunk)| *word = u32::from_le_bytes(chunk.try_into().unwrap()));
+}
+
+#[derive(Clone)]
+/// Represents the original ChaCha20 cipher state as specified by D. J. Bernstein, with a 64-bit
+/// nonce and a 64-bit block counter, for interoperability with legacy protocols.
+pub struct ChaCha20Legacy(ChaCha20);
+
+impl ChaCha20Legacy {
+    #[inline]
+    /// Creates a new ChaCha20Legacy instance with the provided key and nonce.
+    pub fn new(key: &Key, nonce: &LegacyNonce) -> Self {
+        Self::new_with_cnt(key, nonce, 0)
+    }
+
+    #[inline]
+    /// Creates a new ChaCha20Legacy instance with the provided key, nonce, and initial counter value.
+    pub fn new_with_cnt(key: &Key, nonce: &LegacyNonce, cnt: u64) -> Self {
+        Self(ChaCha20 {
+            inner: ChaCha20Inner::new_legacy(key, nonce, cnt),
+            buf: [0; BLOCK_SIZE],
+            available: 0,
+            cleared: false,
+            low_keystream: None,
+        })
+    }
+
+    #[inline]
+    /// Seeks the ChaCha20 stream to a specific position by setting the counter value.
+    pub fn seek_to(&mut self, position: u64) {
+        self.0.inner.seek_to_wide(position);
+        self.0.available = 0;
+    }
+
+    #[inline]
+    /// Returns the current counter value, the counter of the next block to be generated.
+    pub fn current_position(&self) -> u64 {
+        // The legacy counter spans two words, so it always fits in a u64
+        self.0.inner.counter() as u64
+    }
+
+    #[inline]
+    /// Performs the ChaCha20 encryption/decryption on the provided data in-place.
+    pub fn perform_in_place(&mut self, data: &mut [u8]) -> crate::Result<()> {
+        self.0.perform_in_place(data)
+    }
+
+    #[cfg(feature = "alloc")]
+    #[inline]
+    /// Performs the ChaCha20 encryption/decryption on the provided data and returns the result as a new vector.
+    pub fn perform(&mut self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        self.0.perform(data)
+    }
+
+    #[inline]
+    /// Fills `out` with the raw keystream, continuing from the current position.
+    pub fn fill_keystream(&mut self, out: &mut [u8]) -> crate::Result<()> {
+        self.0.fill_keystream(out)
+    }
+
+    #[inline]
+    /// Zeroes the cipher state, see `ChaCha20::clear`.
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
 
     #[test]
@@ -151,7 +716,7 @@ mod test {
             0x87, 0x4d,
         ];
 
-        let mut res = text.clone();
+        let mut res = *text;
 
         let mut cipher = ChaCha20::new_with_cnt(&key, &nonce, 1);
         cipher.perform_in_place(&mut res).unwrap();
@@ -162,6 +727,620 @@ mod test {
         assert_eq!(&res, text);
     }
 
+    #[test]
+    fn new_with_cnt_starts_at_the_counter() {
+        let key: Key = core::array::from_fn(|i| i as u8);
+        let nonce: Nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        // The start of the ciphertext of RFC 8439 section 2.4.2, encrypted from the counter 1
+        let expected_start = [
+            0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80, 0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d,
+            0x69, 0x81,
+        ];
+
+        let mut cipher = ChaCha20::new_with_cnt(&key, &nonce, 1);
+        let mut keystream = [0; 200];
+        cipher.fill_keystream(&mut keystream).unwrap();
+
+        for ((k, c), p) in keystream
+            .iter()
+            .zip(expected_start)
+            .zip(b"Ladies and Gentl")
+        {
+            assert_eq!(*k, c ^ p);
+        }
+
+        let mut skipped = ChaCha20::new(&key, &nonce);
+        skipped.fill_keystream(&mut [0; 64]).unwrap();
+        assert_eq!(skipped.next_bytes::<200>().unwrap(), keystream);
+    }
+
+    #[test]
+    fn new_nonce128() {
+        let key: Key = core::array::from_fn(|i| i as u8);
+        // The counter 1 and the nonce of RFC 8439 section 2.4.2 laid out as a single nonce
+        let nonce = [
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let expected_start = [
+            0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80, 0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d,
+            0x69, 0x81,
+        ];
+
+        let mut text = *b"Ladies and Gentl";
+        ChaCha20::new_nonce128(&key, &nonce)
+            .perform_in_place(&mut text)
+            .unwrap();
+        assert_eq!(text, expected_start);
+
+        // The whole nonce is the counter, carrying from a word into the next one, including
+        // through the four blocks generated at once
+        let mut start = [0; 16];
+        start[..4].copy_from_slice(&(u32::MAX - 1).to_le_bytes());
+        start[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        start[8] = 0x41;
+        let mut carried = [0; 16];
+        carried[8] = 0x42;
+
+        let mut keystream = [0; 5 * 64];
+        ChaCha20::new_nonce128(&key, &start)
+            .fill_keystream(&mut keystream)
+            .unwrap();
+
+        let mut expected = [0; 5 * 64];
+        ChaCha20::new_nonce128(&key, &start)
+            .fill_keystream(&mut expected[..128])
+            .unwrap();
+        ChaCha20::new_nonce128(&key, &carried)
+            .fill_keystream(&mut expected[128..])
+            .unwrap();
+        assert_eq!(keystream, expected);
+
+        // The keystream only ends when the whole nonce wraps around
+        static LAST: ChaCha20 = ChaCha20::new_nonce128(&[0; 32], &[0xff; 16]);
+        let mut cipher = LAST.clone();
+        assert!(cipher.fill_keystream(&mut [0; 64]).is_ok());
+        assert_eq!(
+            cipher.fill_keystream(&mut [0; 1]).unwrap_err(),
+            Error::DataTooLong
+        );
+
+        let mut high = [0xff; 16];
+        high[15] = 0xfe;
+        let mut cipher = ChaCha20::new_nonce128(&key, &high);
+        assert!(cipher.fill_keystream(&mut [0; 64 * 1024]).is_ok());
+    }
+
+    #[test]
+    fn apply_keystream_partial() {
+        let key = [0x3c; 32];
+        let nonce = [0x0d; 12];
+        let input: [u8; 600] = core::array::from_fn(|i| (i * 7) as u8);
+
+        let mut expected = input;
+        ChaCha20::new(&key, &nonce)
+            .perform_in_place(&mut expected)
+            .unwrap();
+
+        let mut cipher = ChaCha20::new(&key, &nonce);
+        let mut result = [0; 600];
+        let mut done = 0;
+
+        for capacity in [1, 7, 64, 3, 100, 13, 256, 65].iter().cycle() {
+            if done == input.len() {
+                break;
+            }
+
+            let mut output = [0xee; 256];
+            let n = cipher
+                .apply_keystream_partial(&input[done..], &mut output[..*capacity])
+                .unwrap();
+
+            assert_eq!(n, (*capacity).min(input.len() - done));
+            assert!(output[n..].iter().all(|&b| b == 0xee));
+            result[done..done + n].copy_from_slice(&output[..n]);
+            done += n;
+        }
+
+        assert_eq!(result, expected);
+
+        // An empty input or output processes nothing
+        let mut output = [0xee; 4];
+        assert_eq!(cipher.apply_keystream_partial(&[], &mut output), Ok(0));
+        assert_eq!(cipher.apply_keystream_partial(&input, &mut []), Ok(0));
+        assert_eq!(output, [0xee; 4]);
+
+        cipher.clear();
+        assert_eq!(
+            cipher.apply_keystream_partial(&input, &mut output),
+            Err(Error::CipherCleared)
+        );
+        assert_eq!(output, [0xee; 4]);
+    }
+
+    #[test]
+    fn fill_keystream_aligned() {
+        #[repr(align(32))]
+        struct Aligned([u8; 300]);
+
+        let key: Key = Default::default();
+        let nonce: Nonce = Default::default();
+        let expected_first_block = [
+            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+            0xbd, 0x28, 0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc,
+            0x8b, 0x77, 0x0d, 0xc7, 0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d, 0x77, 0x24,
+            0xe0, 0x3f, 0xb8, 0xd8, 0x4a, 0x37, 0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c,
+            0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee, 0x65, 0x86,
+        ];
+
+        let mut aligned = Aligned([0xff; 300]);
+        let mut cipher = ChaCha20::new(&key, &nonce);
+        cipher.fill_keystream_aligned(&mut aligned.0).unwrap();
+
+        let mut expected = [0u8; 300];
+        let mut cipher = ChaCha20::new(&key, &nonce);
+        cipher.fill_keystream(&mut expected).unwrap();
+
+        assert_eq!(aligned.0[..BLOCK_SIZE], expected_first_block);
+        assert_eq!(aligned.0, expected);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn next_bytes() {
+        let key: Key = Default::default();
+        let nonce: Nonce = Default::default();
+        // RFC 8439 appendix A.1, test vectors #1 and #2
+        let expected = [
+            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+            0xbd, 0x28, 0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc,
+            0x8b, 0x77, 0x0d, 0xc7, 0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d, 0x77, 0x24,
+            0xe0, 0x3f, 0xb8, 0xd8, 0x4a, 0x37, 0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c,
+            0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee, 0x65, 0x86, 0x9f, 0x07, 0xe7, 0xbe, 0x55, 0x51,
+        ];
+
+        let mut cipher = ChaCha20::new(&key, &nonce);
+        assert_eq!(cipher.next_bytes::<70>().unwrap(), expected);
+        assert_eq!(cipher.next_bytes::<2>().unwrap(), [0x38, 0x7a]);
+    }
+
+    #[test]
+    fn new_from_slices() {
+        let bytes: [u8; 33] = core::array::from_fn(|i| i as u8);
+        let key: Key = bytes[..32].try_into().unwrap();
+        let nonce: Nonce = bytes[..12].try_into().unwrap();
+
+        let mut cipher = ChaCha20::new_from_slices(&bytes[..32], &bytes[..12]).unwrap();
+        assert_eq!(
+            cipher.next_bytes::<64>(),
+            ChaCha20::new(&key, &nonce).next_bytes::<64>()
+        );
+
+        for (key_len, nonce_len) in [(31, 12), (33, 12), (0, 12), (32, 11), (32, 13), (32, 0)] {
+            assert_eq!(
+                ChaCha20::new_from_slices(&bytes[..key_len], &bytes[..nonce_len])
+                    .err()
+                    .unwrap(),
+                Error::InvalidLength
+            );
+        }
+    }
+
+    #[test]
+    fn keystream_bounded() {
+        let key: Key = [0x51; 32];
+        let nonce: Nonce = [0x15; 12];
+
+        let mut expected = [0; 100];
+        ChaCha20::new(&key, &nonce)
+            .fill_keystream(&mut expected)
+            .unwrap();
+
+        let mut cipher = ChaCha20::new(&key, &nonce);
+        let mut keystream = cipher.keystream_bounded(70).unwrap();
+        assert_eq!(keystream.len(), 70);
+        for &byte in &expected[..30] {
+            assert_eq!(keystream.next(), Some(byte));
+        }
+        assert_eq!(keystream.len(), 40);
+        assert!(keystream.by_ref().eq(expected[30..70].iter().copied()));
+        assert_eq!(keystream.len(), 0);
+        assert_eq!(keystream.next(), None);
+        drop(keystream);
+
+        // The cipher continues after the consumed bytes
+        assert!(cipher
+            .keystream_bounded(10)
+            .unwrap()
+            .eq(expected[70..80].iter().copied()));
+
+        // Bytes taken from the cipher but not consumed are not skipped
+        let mut keystream = cipher.keystream_bounded(20).unwrap();
+        assert_eq!(keystream.next(), Some(expected[80]));
+        drop(keystream);
+        assert_eq!(cipher.next_bytes::<19>().unwrap(), expected[81..]);
+
+        let mut cipher = ChaCha20::new_with_cnt(&key, &nonce, u32::MAX);
+        assert_eq!(
+            cipher.keystream_bounded(65).err().unwrap(),
+            Error::DataTooLong
+        );
+        assert_eq!(cipher.keystream_bounded(64).unwrap().count(), 64);
+    }
+
+    #[test]
+    fn fill_keystream_words() {
+        let key: Key = [0x21; 32];
+        let nonce: Nonce = [0x12; 12];
+
+        // Word aligned and misaligned starting positions, ending mid-block and on a boundary
+        for (skip, words) in [(0, 16), (0, 37), (8, 3), (8, 70), (3, 37), (61, 1)] {
+            let mut cipher = ChaCha20::new(&key, &nonce);
+            let mut reference = cipher.clone();
+            cipher.fill_keystream(&mut [0; 64][..skip]).unwrap();
+            reference.fill_keystream(&mut [0; 64][..skip]).unwrap();
+
+            let mut out = [0u32; 70];
+            cipher.fill_keystream_words(&mut out[..words]).unwrap();
+
+            let mut expected = [0; 70 * 4];
+            reference
+                .fill_keystream(&mut expected[..words * 4])
+                .unwrap();
+
+            for (word, bytes) in out[..words].iter().zip(expected.chunks_exact(4)) {
+                assert_eq!(word.to_le_bytes(), bytes);
+            }
+
+            // Both continue from the same position
+            assert_eq!(cipher.next_bytes::<5>(), reference.next_bytes::<5>());
+        }
+
+        let mut cipher = ChaCha20::new_with_cnt(&key, &nonce, u32::MAX);
+        assert_eq!(
+            cipher.fill_keystream_words(&mut [0; 17]).unwrap_err(),
+            Error::DataTooLong
+        );
+        assert!(cipher.fill_keystream_words(&mut [0; 16]).is_ok());
+    }
+
+    #[test]
+    fn fill_keystream_be() {
+        let key: Key = [0x21; 32];
+        let nonce: Nonce = [0x12; 12];
+
+        for (skip, len) in [(0, 64), (0, 4), (0, 200), (8, 60), (3, 132)] {
+            let mut cipher = ChaCha20::new(&key, &nonce);
+            let mut reference = cipher.clone();
+            cipher.fill_keystream(&mut [0; 64][..skip]).unwrap();
+            reference.fill_keystream(&mut [0; 64][..skip]).unwrap();
+
+            let mut out = [0; 200];
+            cipher.fill_keystream_be(&mut out[..len]).unwrap();
+
+            let mut expected = [0; 200];
+            reference.fill_keystream(&mut expected[..len]).unwrap();
+            expected[..len]
+                .chunks_exact_mut(4)
+                .for_each(|word| word.reverse());
+
+            assert_eq!(out[..len], expected[..len]);
+            assert_eq!(cipher.next_bytes::<5>(), reference.next_bytes::<5>());
+        }
+
+        let mut cipher = ChaCha20::new(&key, &nonce);
+        let mut out = [0; 6];
+        assert_eq!(
+            cipher.fill_keystream_be(&mut out).unwrap_err(),
+            Error::InvalidLength
+        );
+        assert_eq!(cipher.cached_keystream_len(), 0);
+        assert_eq!(cipher.current_position(), 0);
+    }
+
+    #[test]
+    fn cached_keystream_len() {
+        let mut cipher = ChaCha20::new(&[0x07; 32], &[0x70; 12]);
+        assert_eq!(cipher.cached_keystream_len(), 0);
+
+        cipher.perform_in_place(&mut [0; 70]).unwrap();
+        assert_eq!(cipher.cached_keystream_len(), 58);
+
+        cipher.perform_in_place(&mut [0; 58]).unwrap();
+        assert_eq!(cipher.cached_keystream_len(), 0);
+
+        cipher.seek_bytes(65).unwrap();
+        assert_eq!(cipher.cached_keystream_len(), 63);
+    }
+
+    #[test]
+    fn operations_after_clear() {
+        let mut cipher = ChaCha20::new(&[0x0c; 32], &[0xc0; 12]);
+        cipher.perform_in_place(&mut [0; 10]).unwrap();
+        cipher.clear();
+
+        assert_eq!(cipher.cached_keystream_len(), 0);
+        assert_eq!(
+            cipher.perform_in_place(&mut [0; 10]).unwrap_err(),
+            Error::CipherCleared
+        );
+        assert_eq!(
+            cipher.fill_keystream(&mut [0; 64]).unwrap_err(),
+            Error::CipherCleared
+        );
+        assert_eq!(
+            cipher.gen_blocks(&mut [[0; BLOCK_SIZE]; 4]).unwrap_err(),
+            Error::CipherCleared
+        );
+        assert_eq!(cipher.seek_bytes(70).unwrap_err(), Error::CipherCleared);
+
+        // Seeking back does not bring the cleared state back to life
+        cipher.seek_to(0);
+        assert_eq!(cipher.next_bytes::<1>().unwrap_err(), Error::CipherCleared);
+
+        let mut legacy = ChaCha20Legacy::new(&[0x0c; 32], &[0xc0; 8]);
+        legacy.clear();
+        assert_eq!(
+            legacy.perform_in_place(&mut [0; 10]).unwrap_err(),
+            Error::CipherCleared
+        );
+    }
+
+    #[test]
+    fn gen_blocks_matches_keystream() {
+        let key: Key = [0x21; 32];
+        let nonce: Nonce = [0x12; 12];
+
+        for (offset, count) in [(0, 0), (0, 1), (0, 4), (0, 11), (5, 6)] {
+            let mut blocks = [[0; BLOCK_SIZE]; 11];
+            let mut expected = [[0; BLOCK_SIZE]; 11];
+
+            let mut cipher = ChaCha20::new_with_cnt(&key, &nonce, 7);
+            cipher.fill_keystream(&mut [0; 5][..offset]).unwrap();
+            cipher.gen_blocks(&mut blocks[..count]).unwrap();
+            let next = cipher.next_bytes::<3>().unwrap();
+
+            let mut reference = ChaCha20::new_with_cnt(&key, &nonce, 7);
+            reference.fill_keystream(&mut [0; 5][..offset]).unwrap();
+            reference
+                .fill_keystream(expected[..count].as_flattened_mut())
+                .unwrap();
+
+            assert_eq!(blocks, expected);
+            assert_eq!(next, reference.next_bytes::<3>().unwrap());
+        }
+
+        let mut cipher = ChaCha20::new_with_cnt(&key, &nonce, u32::MAX - 2);
+        assert_eq!(
+            cipher.gen_blocks(&mut [[0; BLOCK_SIZE]; 4]).unwrap_err(),
+            Error::DataTooLong
+        );
+        assert!(cipher.gen_blocks(&mut [[0; BLOCK_SIZE]; 3]).is_ok());
+    }
+
+    #[test]
+    fn hchacha20_test_vector() {
+        // draft-irtf-cfrg-xchacha-03, section 2.2.1
+        let key: Key = core::array::from_fn(|i| i as u8);
+        let input = [
+            0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00, 0x31, 0x41,
+            0x59, 0x27,
+        ];
+        let expected = [
+            0x82, 0x41, 0x3b, 0x42, 0x27, 0xb2, 0x7b, 0xfe, 0xd3, 0x0e, 0x42, 0x50, 0x8a, 0x87,
+            0x7d, 0x73, 0xa0, 0xf9, 0xe4, 0xd5, 0x8a, 0x74, 0xa8, 0x53, 0xc1, 0x2e, 0xc4, 0x13,
+            0x26, 0xd3, 0xec, 0xdc,
+        ];
+
+        assert_eq!(hchacha20(&key, &input), expected);
+    }
+
+    #[cfg(feature = "debug-internals")]
+    #[test]
+    #[rustfmt::skip]
+    fn block_with_intermediate() {
+        let key: Key = core::array::from_fn(|i| i as u8);
+        let nonce: Nonce = [0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00];
+
+        // RFC 8439 section 2.3.2, state after the 20 rounds and serialized block
+        let expected_working_state = [
+            0x837778ab, 0xe238d763, 0xa67ae21e, 0x5950bb2f,
+            0xc4f2d0c7, 0xfc62bb2f, 0x8fa018fc, 0x3f5ec7b7,
+            0x335271c2, 0xf29489f3, 0xeabda8fc, 0x82e46ebd,
+            0xd19c12b4, 0xb04e16de, 0x9e83d0cb, 0x4e3c50a2,
+        ];
+        let expected_block = [
+            0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71, 0xc4,
+            0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03, 0x04, 0x22, 0xaa, 0x9a, 0xc3, 0xd4, 0x6c, 0x4e,
+            0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09, 0x14, 0xc2, 0xd7, 0x05, 0xd9, 0x8b, 0x02, 0xa2,
+            0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9, 0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50, 0x3c, 0x4e,
+        ];
+
+        let (working_state, block) = ChaCha20::block_with_intermediate(&key, &nonce, 1);
+        assert_eq!(working_state, expected_working_state);
+        assert_eq!(block, expected_block);
+    }
+
+    #[test]
+    fn const_constructor() {
+        static CIPHER: ChaCha20 = ChaCha20::new(&[0; 32], &[0; 12]);
+
+        let mut cipher = CIPHER.clone();
+        // RFC 8439 appendix A.1, test vector #1
+        assert_eq!(cipher.next_bytes::<4>().unwrap(), [0x76, 0xb8, 0xe0, 0xad]);
+        assert_eq!(
+            CIPHER.clone().next_bytes::<64>().unwrap(),
+            ChaCha20::new(&[0; 32], &[0; 12])
+                .next_bytes::<64>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn seek64() {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+
+        let mut expected = [0u8; 300];
+        ChaCha20::new(&key, &nonce)
+            .fill_keystream(&mut expected)
+            .unwrap();
+
+        let mut cipher = ChaCha20::new(&key, &nonce);
+        assert_eq!(cipher.seek64(200).unwrap(), (3, 8));
+
+        let mut keystream = [0u8; 100];
+        cipher.fill_keystream(&mut keystream).unwrap();
+        assert_eq!(keystream, expected[200..]);
+
+        assert_eq!(cipher.seek64(128).unwrap(), (2, 0));
+        assert_eq!(cipher.current_position(), 2);
+
+        let max = (u64::from(u32::MAX) + 1) * BLOCK_SIZE as u64;
+        assert_eq!(cipher.seek64(max - 1).unwrap(), (u32::MAX, 63));
+        assert_eq!(cipher.seek64(max).unwrap_err(), Error::DataTooLong);
+    }
+
+    #[test]
+    fn seek_bytes_random_access() {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+        let plaintext = [0x5a; 1000];
+
+        let mut ciphertext = plaintext;
+        ChaCha20::new(&key, &nonce)
+            .perform_in_place(&mut ciphertext)
+            .unwrap();
+
+        let mut cipher = ChaCha20::new(&key, &nonce);
+        for offset in [0, 1, 63, 64, 65, 333, 999] {
+            let mut chunk = [0u8; 1];
+            chunk.copy_from_slice(&ciphertext[offset..offset + 1]);
+
+            cipher.seek_bytes(offset as u64).unwrap();
+            cipher.perform_in_place(&mut chunk).unwrap();
+            assert_eq!(chunk[0], plaintext[offset]);
+        }
+
+        cipher.seek_bytes(517).unwrap();
+        let mut rest = [0u8; 483];
+        rest.copy_from_slice(&ciphertext[517..]);
+        cipher.perform_in_place(&mut rest).unwrap();
+        assert_eq!(rest, plaintext[517..]);
+    }
+
+    #[test]
+    fn rekey() {
+        let nonce: Nonce = [0x4b; 12];
+        let mut ratchet = ChaCha20::new(&[0x01; 32], &nonce);
+        let mut reference_key: Key = [0x01; 32];
+
+        for _ in 0..3 {
+            ratchet.perform_in_place(&mut [0; 10]).unwrap();
+            let next_key: Key = ratchet.next_bytes().unwrap();
+            ratchet.rekey(&next_key, &nonce);
+
+            let mut reference = ChaCha20::new(&reference_key, &nonce);
+            reference.perform_in_place(&mut [0; 10]).unwrap();
+            reference_key = reference.next_bytes().unwrap();
+            assert_eq!(next_key, reference_key);
+
+            assert_eq!(ratchet.current_position(), 0);
+            assert_eq!(ratchet.cached_keystream_len(), 0);
+            assert_eq!(
+                ratchet.clone().next_bytes::<100>().unwrap(),
+                ChaCha20::new(&next_key, &nonce)
+                    .next_bytes::<100>()
+                    .unwrap()
+            );
+        }
+
+        ratchet.clear();
+        ratchet.rekey(&[0x02; 32], &nonce);
+        assert!(ratchet.perform_in_place(&mut [0; 10]).is_ok());
+    }
+
+    #[test]
+    fn low_keystream_callback() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static FIRED: AtomicUsize = AtomicUsize::new(0);
+        fn on_low_keystream() {
+            FIRED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // Two blocks, 128 bytes, are left before the counter runs out
+        let mut cipher = ChaCha20::new_with_cnt(&[0x39; 32], &[0x93; 12], u32::MAX - 1);
+        cipher.set_low_keystream_callback(100, on_low_keystream);
+
+        cipher.perform_in_place(&mut [0; 28]).unwrap();
+        assert_eq!(FIRED.load(Ordering::Relaxed), 0);
+
+        // 99 bytes are left
+        cipher.perform_in_place(&mut [0; 1]).unwrap();
+        assert_eq!(FIRED.load(Ordering::Relaxed), 1);
+
+        cipher.perform_in_place(&mut [0; 50]).unwrap();
+        cipher.fill_keystream(&mut [0; 49]).unwrap();
+        assert_eq!(FIRED.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn rekey_preserve_position() {
+        let old_key: Key = [0x42; 32];
+        let new_key: Key = [0x24; 32];
+        let nonce: Nonce = [0x11; 12];
+
+        for position in [64, 100] {
+            let mut cipher = ChaCha20::new_with_cnt(&old_key, &nonce, 1);
+            let mut data = [0u8; 100];
+            cipher.fill_keystream(&mut data[..position]).unwrap();
+
+            cipher.rekey_preserve_position(&new_key);
+            let mut keystream = [0u8; 150];
+            cipher.fill_keystream(&mut keystream).unwrap();
+
+            let mut fresh = ChaCha20::new(&new_key, &nonce);
+            fresh.seek_bytes((BLOCK_SIZE + position) as u64).unwrap();
+            let mut expected = [0u8; 150];
+            fresh.fill_keystream(&mut expected).unwrap();
+
+            assert_eq!(keystream, expected);
+        }
+    }
+
+    #[test]
+    fn counter_overflow() {
+        let key: Key = Default::default();
+        let nonce: Nonce = Default::default();
+
+        let mut cipher = ChaCha20::new_with_cnt(&key, &nonce, u32::MAX);
+        let mut data = [0u8; BLOCK_SIZE + 1];
+        assert_eq!(
+            cipher.perform_in_place(&mut data).unwrap_err(),
+            Error::DataTooLong
+        );
+        assert_eq!(data, [0u8; BLOCK_SIZE + 1]);
+
+        // The last block can still be used, but nothing after it
+        cipher
+            .perform_in_place(&mut data[..BLOCK_SIZE - 1])
+            .unwrap();
+        cipher.perform_in_place(&mut data[..1]).unwrap();
+        assert_eq!(
+            cipher.perform_in_place(&mut data[..1]).unwrap_err(),
+            Error::DataTooLong
+        );
+
+        let mut cipher = ChaCha20::new_with_cnt(&key, &nonce, u32::MAX - 1);
+        let mut data = [0u8; BLOCK_SIZE * 2];
+        cipher.perform_in_place(&mut data).unwrap();
+        assert_eq!(
+            cipher.fill_keystream(&mut data[..1]).unwrap_err(),
+            Error::DataTooLong
+        );
+    }
+
     #[test]
     fn rfc_8439_block_function_1() {
         let key: Key = Default::default();
@@ -295,7 +1474,7 @@ mod test {
         nonce[11] = 0x02;
 
         let plaintext = b"Any submission to the IETF intended by the Contributor for publication as all or part of an IETF Internet-Draft or RFC and any statement made within the context of an IETF activity is considered an \"IETF Contribution\". Such statements include oral statements in IETF sessions, as well as written and electronic communications made at any time or place, which are addressed to";
-        let mut plaintext = plaintext.clone();
+        let mut plaintext = *plaintext;
         let expected_ciphertext = [
             0xa3, 0xfb, 0xf0, 0x7d, 0xf3, 0xfa, 0x2f, 0xde, 0x4f, 0x37, 0x6c, 0xa2, 0x3e, 0x82,
             0x73, 0x70, 0x41, 0x60, 0x5d, 0x9f, 0x4f, 0x4f, 0x57, 0xbd, 0x8c, 0xff, 0x2c, 0x1d,
@@ -373,4 +1552,133 @@ mod test {
 
         assert_eq!(plaintext, expected_ciphertext);
     }
+
+    /// Small xorshift generator, good enough to produce test inputs
+    #[cfg(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        all(target_arch = "aarch64", target_endian = "little"),
+        feature = "rand_core"
+    ))]
+    pub(crate) fn next_random(seed: &mut u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed
+    }
+
+    /// Deterministic xorshift RNG used to test the generation helpers
+    #[cfg(feature = "rand_core")]
+    pub(crate) struct TestRng(pub(crate) u64);
+
+    #[cfg(feature = "rand_core")]
+    impl rand_core::RngCore for TestRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            next_random(&mut self.0)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            rand_core::impls::fill_bytes_via_next(self, dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn generate_key_and_nonce() {
+        let mut rng = TestRng(0x853c49e6748fea9b);
+
+        let key = ChaCha20::generate_key(&mut rng);
+        let other_key = ChaCha20::generate_key(&mut rng);
+        assert_ne!(key, other_key);
+        // the last bytes are filled as well
+        assert_ne!(key[24..], [0; 8]);
+
+        let nonce = ChaCha20::generate_nonce(&mut rng);
+        let other_nonce = ChaCha20::generate_nonce(&mut rng);
+        assert_ne!(nonce, other_nonce);
+        assert_ne!(nonce[8..], [0; 4]);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn legacy_test_vectors() {
+        // Test vectors of the original ChaCha20 from draft-agl-tls-chacha20poly1305-04
+        let key: Key = core::array::from_fn(|i| i as u8);
+        let nonce: LegacyNonce = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+        let expected = [
+            0xf7, 0x98, 0xa1, 0x89, 0xf1, 0x95, 0xe6, 0x69, 0x82, 0x10, 0x5f, 0xfb, 0x64, 0x0b,
+            0xb7, 0x75, 0x7f, 0x57, 0x9d, 0xa3, 0x16, 0x02, 0xfc, 0x93, 0xec, 0x01, 0xac, 0x56,
+            0xf8, 0x5a, 0xc3, 0xc1, 0x34, 0xa4, 0x54, 0x7b, 0x73, 0x3b, 0x46, 0x41, 0x30, 0x42,
+            0xc9, 0x44, 0x00, 0x49, 0x17, 0x69, 0x05, 0xd3, 0xbe, 0x59, 0xea, 0x1c, 0x53, 0xf1,
+            0x59, 0x16, 0x15, 0x5c, 0x2b, 0xe8, 0x24, 0x1a, 0x38, 0x00, 0x8b, 0x9a, 0x26, 0xbc,
+            0x35, 0x94, 0x1e, 0x24, 0x44, 0x17, 0x7c, 0x8a, 0xde, 0x66, 0x89, 0xde, 0x95, 0x26,
+            0x49, 0x86, 0xd9, 0x58, 0x89, 0xfb, 0x60, 0xe8, 0x46, 0x29, 0xc9, 0xbd, 0x9a, 0x5a,
+            0xcb, 0x1c, 0xc1, 0x18, 0xbe, 0x56, 0x3e, 0xb9, 0xb3, 0xa4, 0xa4, 0x72, 0xf8, 0x2e,
+            0x09, 0xa7, 0xe7, 0x78, 0x49, 0x2b, 0x56, 0x2e, 0xf7, 0x13, 0x0e, 0x88, 0xdf, 0xe0,
+            0x31, 0xc7, 0x9d, 0xb9, 0xd4, 0xf7, 0xc7, 0xa8, 0x99, 0x15, 0x1b, 0x9a, 0x47, 0x50,
+            0x32, 0xb6, 0x3f, 0xc3, 0x85, 0x24, 0x5f, 0xe0, 0x54, 0xe3, 0xdd, 0x5a, 0x97, 0xa5,
+            0xf5, 0x76, 0xfe, 0x06, 0x40, 0x25, 0xd3, 0xce, 0x04, 0x2c, 0x56, 0x6a, 0xb2, 0xc5,
+            0x07, 0xb1, 0x38, 0xdb, 0x85, 0x3e, 0x3d, 0x69, 0x59, 0x66, 0x09, 0x96, 0x54, 0x6c,
+            0xc9, 0xc4, 0xa6, 0xea, 0xfd, 0xc7, 0x77, 0xc0, 0x40, 0xd7, 0x0e, 0xaf, 0x46, 0xf7,
+            0x6d, 0xad, 0x39, 0x79, 0xe5, 0xc5, 0x36, 0x0c, 0x33, 0x17, 0x16, 0x6a, 0x1c, 0x89,
+            0x4c, 0x94, 0xa3, 0x71, 0x87, 0x6a, 0x94, 0xdf, 0x76, 0x28, 0xfe, 0x4e, 0xaa, 0xf2,
+            0xcc, 0xb2, 0x7d, 0x5a, 0xaa, 0xe0, 0xad, 0x7a, 0xd0, 0xf9, 0xd4, 0xb6, 0xad, 0x3b,
+            0x54, 0x09, 0x87, 0x46, 0xd4, 0x52, 0x4d, 0x38, 0x40, 0x7a, 0x6d, 0xeb, 0x3a, 0xb7,
+            0x8f, 0xab, 0x78, 0xc9,
+        ];
+
+        let mut out = [0u8; 256];
+        ChaCha20Legacy::new(&key, &nonce).fill_keystream(&mut out).unwrap();
+        assert_eq!(out, expected);
+
+        let expected = [
+            0xef, 0x3f, 0xdf, 0xd6, 0xc6, 0x15, 0x78, 0xfb, 0xf5, 0xcf, 0x35, 0xbd, 0x3d, 0xd3,
+            0x3b, 0x80, 0x09, 0x63, 0x16, 0x34, 0xd2, 0x1e, 0x42, 0xac, 0x33, 0x96, 0x0b, 0xd1,
+            0x38, 0xe5, 0x0d, 0x32, 0x11, 0x1e, 0x4c, 0xaf, 0x23, 0x7e, 0xe5, 0x3c, 0xa8, 0xad,
+            0x64, 0x26, 0x19, 0x4a, 0x88, 0x54, 0x5d, 0xdc, 0x49, 0x7a, 0x0b, 0x46, 0x6e, 0x7d,
+            0x6b, 0xbd, 0xb0, 0x04, 0x1b, 0x2f, 0x58, 0x6b,
+        ];
+
+        let mut out = [0u8; 64];
+        ChaCha20Legacy::new(&[0; 32], &[0x01, 0, 0, 0, 0, 0, 0, 0])
+            .fill_keystream(&mut out)
+            .unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn legacy_counter_carries_into_high_word() {
+        let key: Key = [0x42; 32];
+        let nonce: LegacyNonce = [0x24; 8];
+
+        let mut out = [0u8; 6 * BLOCK_SIZE];
+        let mut cipher = ChaCha20Legacy::new_with_cnt(&key, &nonce, 0xffff_fffd);
+        cipher.fill_keystream(&mut out).unwrap();
+        assert_eq!(cipher.current_position(), 0x1_0000_0003);
+
+        // The blocks after the carry are the ones of the IETF layout with the high word in the nonce
+        let mut ietf_nonce = [0x24; 12];
+        ietf_nonce[..4].copy_from_slice(&1u32.to_le_bytes());
+        let mut expected = [0u8; 3 * BLOCK_SIZE];
+        ChaCha20::new_with_cnt(&key, &ietf_nonce, 0)
+            .fill_keystream(&mut expected)
+            .unwrap();
+        assert_eq!(out[3 * BLOCK_SIZE..], expected);
+
+        let mut cipher = ChaCha20Legacy::new_with_cnt(&key, &nonce, u64::MAX);
+        assert!(cipher.fill_keystream(&mut out[..BLOCK_SIZE]).is_ok());
+        assert_eq!(
+            cipher.fill_keystream(&mut out[..1]).unwrap_err(),
+            Error::DataTooLong
+        );
+    }
 }
@@ -0,0 +1,183 @@
+//! AVX2 backend generating four ChaCha20 blocks at once.
+//!
+//! Each 256-bit register holds the same row of two consecutive blocks, so two sets of four
+//! registers cover four blocks. The quarter round is applied to whole rows, and the diagonal
+//! round is done by rotating the words of the rows before and after it.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use super::{BLOCK_SIZE, NUMBER_OF_ROUND, STATE_BLOCK_SIZE};
+
+// Runtime detection is always enabled in tests so the backend gets exercised
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+/// Returns whether the AVX2 backend can be used on the running CPU
+#[inline(always)]
+pub(crate) fn is_available() -> bool {
+    #[cfg(any(feature = "std", test))]
+    {
+        std::is_x86_feature_detected!("avx2")
+    }
+
+    #[cfg(not(any(feature = "std", test)))]
+    {
+        cfg!(target_feature = "avx2")
+    }
+}
+
+/// Generates the four consecutive blocks starting at the counter of `state` into `out`.
+/// The counter of `state` is not advanced.
+///
+/// # Safety
+///
+/// The CPU must support AVX2, see `is_available`.
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn gen_4_blocks(state: &[u32; STATE_BLOCK_SIZE], out: &mut [u8; 4 * BLOCK_SIZE]) {
+    let s = state.map(|v| v as i32);
+    let cnt = [0, 1, 2, 3].map(|i| state[12].wrapping_add(i) as i32);
+
+    let a = _mm256_setr_epi32(s[0], s[1], s[2], s[3], s[0], s[1], s[2], s[3]);
+    let b = _mm256_setr_epi32(s[4], s[5], s[6], s[7], s[4], s[5], s[6], s[7]);
+    let c = _mm256_setr_epi32(s[8], s[9], s[10], s[11], s[8], s[9], s[10], s[11]);
+    let d0 = _mm256_setr_epi32(cnt[0], s[13], s[14], s[15], cnt[1], s[13], s[14], s[15]);
+    let d1 = _mm256_setr_epi32(cnt[2], s[13], s[14], s[15], cnt[3], s[13], s[14], s[15]);
+
+    let mut x0 = [a, b, c, d0];
+    let mut x1 = [a, b, c, d1];
+
+    for _ in 0..(NUMBER_OF_ROUND / 2) {
+        // column rounds
+        quarter_round(&mut x0);
+        quarter_round(&mut x1);
+
+        // diagonal rounds
+        diagonalize(&mut x0);
+        diagonalize(&mut x1);
+        quarter_round(&mut x0);
+        quarter_round(&mut x1);
+        undiagonalize(&mut x0);
+        undiagonalize(&mut x1);
+    }
+
+    let initial = [[a, b, c, d0], [a, b, c, d1]];
+
+    for (i, (x, init)) in [x0, x1].iter().zip(&initial).enumerate() {
+        let [a, b, c, d] = [0, 1, 2, 3].map(|row| _mm256_add_epi32(x[row], init[row]));
+        let out = &mut out[i * 2 * BLOCK_SIZE..];
+
+        // The low lanes hold the first block and the high lanes the second one
+        let rows = [
+            _mm256_permute2x128_si256(a, b, 0x20),
+            _mm256_permute2x128_si256(c, d, 0x20),
+            _mm256_permute2x128_si256(a, b, 0x31),
+            _mm256_permute2x128_si256(c, d, 0x31),
+        ];
+
+        for (chunk, row) in out.chunks_exact_mut(32).zip(rows) {
+            _mm256_storeu_si256(chunk.as_mut_ptr() as *mut __m256i, row);
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn quarter_round(x: &mut [__m256i; 4]) {
+    let [mut a, mut b, mut c, mut d] = *x;
+
+    a = _mm256_add_epi32(a, b);
+    d = rotate_left_16(_mm256_xor_si256(d, a));
+
+    c = _mm256_add_epi32(c, d);
+    b = rotate_left::<12, 20>(_mm256_xor_si256(b, c));
+
+    a = _mm256_add_epi32(a, b);
+    d = rotate_left_8(_mm256_xor_si256(d, a));
+
+    c = _mm256_add_epi32(c, d);
+    b = rotate_left::<7, 25>(_mm256_xor_si256(b, c));
+
+    *x = [a, b, c, d];
+}
+
+/// Rotates the words of the rows so the diagonals line up as columns
+#[inline(always)]
+unsafe fn diagonalize(x: &mut [__m256i; 4]) {
+    x[1] = _mm256_shuffle_epi32(x[1], 0b00_11_10_01);
+    x[2] = _mm256_shuffle_epi32(x[2], 0b01_00_11_10);
+    x[3] = _mm256_shuffle_epi32(x[3], 0b10_01_00_11);
+}
+
+#[inline(always)]
+unsafe fn undiagonalize(x: &mut [__m256i; 4]) {
+    x[1] = _mm256_shuffle_epi32(x[1], 0b10_01_00_11);
+    x[2] = _mm256_shuffle_epi32(x[2], 0b01_00_11_10);
+    x[3] = _mm256_shuffle_epi32(x[3], 0b00_11_10_01);
+}
+
+#[inline(always)]
+unsafe fn rotate_left<const L: i32, const R: i32>(x: __m256i) -> __m256i {
+    _mm256_or_si256(_mm256_slli_epi32::<L>(x), _mm256_srli_epi32::<R>(x))
+}
+
+#[inline(always)]
+#[rustfmt::skip]
+unsafe fn rotate_left_16(x: __m256i) -> __m256i {
+    _mm256_shuffle_epi8(x, _mm256_setr_epi8(
+        2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9, 14, 15, 12, 13,
+        2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9, 14, 15, 12, 13,
+    ))
+}
+
+#[inline(always)]
+#[rustfmt::skip]
+unsafe fn rotate_left_8(x: __m256i) -> __m256i {
+    _mm256_shuffle_epi8(x, _mm256_setr_epi8(
+        3, 0, 1, 2, 7, 4, 5, 6, 11, 8, 9, 10, 15, 12, 13, 14,
+        3, 0, 1, 2, 7, 4, 5, 6, 11, 8, 9, 10, 15, 12, 13, 14,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chacha20::test::next_random;
+    use crate::chacha20::ChaCha20Inner;
+
+    #[test]
+    fn matches_scalar_backend() {
+        if !is_available() {
+            return;
+        }
+
+        let mut seed = 0x9e3779b97f4a7c15;
+
+        for _ in 0..256 {
+            let mut key = [0u8; 32];
+            let mut nonce = [0u8; 12];
+            key.iter_mut()
+                .for_each(|v| *v = next_random(&mut seed) as u8);
+            nonce
+                .iter_mut()
+                .for_each(|v| *v = next_random(&mut seed) as u8);
+            // Keep at least four blocks before the counter wraps around
+            let cnt = (next_random(&mut seed) as u32).min(u32::MAX - 3);
+
+            let mut scalar = ChaCha20Inner::new_with_cnt(&key, &nonce, cnt);
+            let mut expected = [0u8; 4 * BLOCK_SIZE];
+            for chunk in expected.chunks_exact_mut(BLOCK_SIZE) {
+                scalar.gen_block(chunk.try_into().unwrap());
+            }
+
+            let mut simd = ChaCha20Inner::new_with_cnt(&key, &nonce, cnt);
+            let mut out = [0u8; 4 * BLOCK_SIZE];
+            simd.gen_4_blocks(&mut out);
+
+            assert_eq!(out, expected);
+            assert_eq!(simd.current_position(), scalar.current_position());
+            assert_eq!(simd.remaining_blocks(), scalar.remaining_blocks());
+        }
+    }
+}
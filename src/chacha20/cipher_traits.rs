@@ -0,0 +1,113 @@
+use super::*;
+use cipher::consts::{U12, U32};
+use cipher::inout::InOutBuf;
+use cipher::{
+    IvSizeUser, KeyIvInit, KeySizeUser, OverflowError, SeekNum, StreamCipher, StreamCipherError,
+    StreamCipherSeek,
+};
+
+impl KeySizeUser for ChaCha20 {
+    type KeySize = U32;
+}
+
+impl IvSizeUser for ChaCha20 {
+    type IvSize = U12;
+}
+
+impl KeyIvInit for ChaCha20 {
+    fn new(key: &cipher::Key<Self>, iv: &cipher::Iv<Self>) -> Self {
+        let mut key: Key = (*key).into();
+        let cipher = ChaCha20::new(&key, &(*iv).into());
+        key.zeroize();
+        cipher
+    }
+}
+
+impl StreamCipher for ChaCha20 {
+    fn try_apply_keystream_inout(
+        &mut self,
+        mut buf: InOutBuf<'_, '_, u8>,
+    ) -> Result<(), StreamCipherError> {
+        // Checked upfront so that nothing is processed when the keystream is too short
+        self.check_keystream_len(buf.len())
+            .map_err(|_| StreamCipherError)?;
+
+        let mut keystream = [0; 4 * BLOCK_SIZE];
+
+        while !buf.is_empty() {
+            let len = buf.len().min(keystream.len());
+            let (mut chunk, rest) = buf.split_at(len);
+
+            // Unwrap here is safe because the keystream length has been checked above
+            self.fill_keystream(&mut keystream[..len]).unwrap();
+            chunk.xor_in2out(&keystream[..len]);
+            buf = rest;
+        }
+
+        keystream.zeroize();
+        Ok(())
+    }
+}
+
+impl StreamCipherSeek for ChaCha20 {
+    fn try_current_pos<T: SeekNum>(&self) -> Result<T, OverflowError> {
+        // The block counter points to the block after the cached keystream
+        let byte = (BLOCK_SIZE - self.available) % BLOCK_SIZE;
+        T::from_block_byte(self.inner.current_position(), byte as u8, BLOCK_SIZE as u8)
+    }
+
+    fn try_seek<T: SeekNum>(&mut self, pos: T) -> Result<(), StreamCipherError> {
+        let (block, byte): (u32, u8) = pos.into_block_byte(BLOCK_SIZE as u8)?;
+
+        self.seek64(u64::from(block) * BLOCK_SIZE as u64 + u64::from(byte))
+            .map(|_| ())
+            .map_err(|_| StreamCipherError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stream_cipher_round_trip() {
+        let key = cipher::Key::<ChaCha20>::from([0x42; 32]);
+        let iv = cipher::Iv::<ChaCha20>::from([0x24; 12]);
+        let text = [0x5a; 300];
+
+        let mut cipher = <ChaCha20 as KeyIvInit>::new(&key, &iv);
+        let mut buffer = text;
+        cipher.apply_keystream(&mut buffer[..7]);
+        cipher.apply_keystream(&mut buffer[7..]);
+        assert_ne!(buffer, text);
+        assert_eq!(cipher.current_pos::<u64>(), 300);
+
+        let mut expected = text;
+        ChaCha20::new(&[0x42; 32], &[0x24; 12])
+            .perform_in_place(&mut expected)
+            .unwrap();
+        assert_eq!(buffer, expected);
+
+        let mut decrypted = [0; 300];
+        cipher.seek(0u64);
+        cipher.apply_keystream_b2b(&buffer, &mut decrypted).unwrap();
+        assert_eq!(decrypted, text);
+
+        // Seeking into the middle of a block
+        cipher.seek(70u32);
+        assert_eq!(cipher.current_pos::<u32>(), 70);
+        let mut tail = buffer;
+        cipher.apply_keystream(&mut tail[70..]);
+        assert_eq!(tail[70..], text[70..]);
+    }
+
+    #[test]
+    fn stream_cipher_keystream_exhausted() {
+        let mut cipher = ChaCha20::new_with_cnt(&[0x42; 32], &[0x24; 12], u32::MAX);
+        let mut buffer = [0; 65];
+
+        assert!(cipher.try_apply_keystream(&mut buffer).is_err());
+        assert_eq!(buffer, [0; 65]);
+        assert!(cipher.try_apply_keystream(&mut buffer[..64]).is_ok());
+    }
+}
@@ -6,6 +6,17 @@ type State = [u32; STATE_BLOCK_SIZE];
 #[derive(Clone)]
 pub struct ChaCha20Inner {
     state: State,
+    /// Set once the block with the last counter value has been generated
+    exhausted: bool,
+    /// Number of state words spanned by the counter from the word 12: 1 for RFC 8439, 2 for the
+    /// original 64-bit nonce variant and 4 for the 128-bit nonce layout
+    counter_words: usize,
+}
+
+/// Reads the little-endian `u32` starting at `at`, usable in const contexts
+#[inline(always)]
+const fn u32_from_le(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
 }
 
 impl Drop for ChaCha20Inner {
@@ -16,34 +27,84 @@ impl Drop for ChaCha20Inner {
 
 impl ChaCha20Inner {
     #[inline]
-    pub(crate) fn new_with_cnt(key: &Key, nonce: &Nonce, cnt: u32) -> Self {
-        let keys_u32 = key
-            .chunks_exact(4)
-            .map(|v| u32::from_le_bytes(v.try_into().unwrap()));
+    pub(crate) const fn new_with_cnt(key: &Key, nonce: &Nonce, cnt: u32) -> Self {
+        let mut state = [0; STATE_BLOCK_SIZE];
 
-        let nonces_u32 = nonce
-            .chunks_exact(4)
-            .map(|v| u32::from_le_bytes(v.try_into().unwrap()));
+        state[0] = INIT_CONSTANTS[0];
+        state[1] = INIT_CONSTANTS[1];
+        state[2] = INIT_CONSTANTS[2];
+        state[3] = INIT_CONSTANTS[3];
 
-        let mut state = [0; STATE_BLOCK_SIZE];
+        // plain loops instead of iterators to keep the constructor usable in const contexts
+        let mut i = 0;
+        while i < 8 {
+            state[4 + i] = u32_from_le(key, i * 4);
+            i += 1;
+        }
 
-        state[0..4].copy_from_slice(&INIT_CONSTANTS);
-        state[4..12]
-            .iter_mut()
-            .zip(keys_u32)
-            .for_each(|(val, key)| *val = key);
         state[12] = cnt;
-        state[13..16]
+
+        let mut i = 0;
+        while i < 3 {
+            state[13 + i] = u32_from_le(nonce, i * 4);
+            i += 1;
+        }
+
+        Self {
+            state,
+            exhausted: false,
+            counter_words: 1,
+        }
+    }
+
+    /// Creates the state of the 128-bit nonce layout, where the nonce fills the words 12 to 15
+    /// and is incremented as a whole as the block counter.
+    #[inline]
+    pub(crate) const fn new_nonce128(key: &Key, nonce: &[u8; 16]) -> Self {
+        let mut res = Self::new_with_cnt(key, &[0; 12], 0);
+
+        let mut i = 0;
+        while i < 4 {
+            res.state[12 + i] = u32_from_le(nonce, i * 4);
+            i += 1;
+        }
+
+        res.counter_words = 4;
+        res
+    }
+
+    /// Creates the state of the original ChaCha20 variant, with a 64-bit counter in the words 12
+    /// and 13 followed by a 64-bit nonce.
+    #[inline]
+    pub(crate) fn new_legacy(key: &Key, nonce: &LegacyNonce, cnt: u64) -> Self {
+        let mut ietf_nonce: Nonce = Default::default();
+        ietf_nonce[..4].copy_from_slice(&((cnt >> 32) as u32).to_le_bytes());
+        ietf_nonce[4..].copy_from_slice(nonce);
+
+        let mut res = Self::new_with_cnt(key, &ietf_nonce, cnt as u32);
+        res.counter_words = 2;
+        res
+    }
+
+    #[inline]
+    pub(crate) fn set_key(&mut self, key: &Key) {
+        self.state[4..12]
             .iter_mut()
-            .zip(nonces_u32)
-            .for_each(|(val, nonce)| *val = nonce);
+            .zip(key.chunks_exact(4))
+            .for_each(|(val, key)| *val = u32::from_le_bytes(key.try_into().unwrap()));
+    }
 
-        Self { state }
+    /// Zeroes the whole state and marks the keystream as exhausted
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.state.zeroize();
+        self.exhausted = true;
     }
 
     #[inline(always)]
     pub(crate) fn seek_to(&mut self, position: u32) {
         self.state[12] = position;
+        self.exhausted = false;
     }
 
     #[inline(always)]
@@ -51,12 +112,63 @@ impl ChaCha20Inner {
         self.state[12]
     }
 
+    /// Sets the counter, including its high words when the counter spans several words
     #[inline(always)]
-    pub(crate) fn gen_block(&mut self, block: &mut Block) {
-        let output = self.full_round();
-        // Increase the counter by one (modulo 2^32)
-        self.state[12] = self.state[12].wrapping_add(1);
+    pub(crate) fn seek_to_wide(&mut self, position: u64) {
+        self.set_counter(u128::from(position));
+        self.exhausted = false;
+    }
 
+    /// Returns the counter, including its high words when the counter spans several words
+    #[inline(always)]
+    pub(crate) fn counter(&self) -> u128 {
+        self.state[12..12 + self.counter_words]
+            .iter()
+            .rev()
+            .fold(0, |counter, &word| counter << 32 | u128::from(word))
+    }
+
+    #[inline(always)]
+    fn set_counter(&mut self, counter: u128) {
+        for (i, word) in self.state[12..12 + self.counter_words]
+            .iter_mut()
+            .enumerate()
+        {
+            *word = (counter >> (32 * i)) as u32;
+        }
+    }
+
+    #[inline(always)]
+    fn max_counter(&self) -> u128 {
+        u128::MAX >> (128 - 32 * self.counter_words)
+    }
+
+    /// Moves the counter `blocks` forward, marking the keystream as exhausted when the last
+    /// counter value has been used. The wrapped counter is never used afterward.
+    #[inline(always)]
+    fn advance(&mut self, blocks: u64) {
+        let counter = self.counter();
+        let max = self.max_counter();
+
+        self.exhausted = counter == max - u128::from(blocks - 1);
+        self.set_counter(counter.wrapping_add(u128::from(blocks)) & max);
+    }
+
+    /// Number of blocks that can still be generated before the counter wraps around,
+    /// saturated to `u64::MAX` for the counters wider than 32 bits
+    #[inline(always)]
+    pub(crate) fn remaining_blocks(&self) -> u64 {
+        if self.exhausted {
+            0
+        } else {
+            let remaining = (self.max_counter() - self.counter()).saturating_add(1);
+            u64::try_from(remaining).unwrap_or(u64::MAX)
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn gen_block(&mut self, block: &mut Block) {
+        let output = self.gen_block_words();
         let serialized_output = output.into_iter().flat_map(|v| v.to_le_bytes());
 
         block
@@ -65,8 +177,96 @@ impl ChaCha20Inner {
             .for_each(|(s1, s0)| *s1 = s0);
     }
 
+    /// Generates the next block as the output words of the block function, before serialization
+    #[inline(always)]
+    pub(crate) fn gen_block_words(&mut self) -> State {
+        debug_assert!(!self.exhausted, "ChaCha20 keystream is exhausted");
+
+        let output = self.full_round();
+        self.advance(1);
+        output
+    }
+
+    /// Generates the next four blocks at once, using a SIMD backend when the CPU supports one.
+    /// At least four blocks must remain in the keystream.
+    #[inline]
+    pub(crate) fn gen_4_blocks(&mut self, out: &mut [u8; 4 * BLOCK_SIZE]) {
+        debug_assert!(
+            self.remaining_blocks() >= 4,
+            "ChaCha20 keystream is exhausted"
+        );
+
+        // The SIMD backends do not carry the counter into the word 13
+        if self.state[12] > u32::MAX - 3 {
+            return self.gen_4_blocks_scalar(out);
+        }
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if super::avx2::is_available() {
+            // Safe because the CPU has been checked to support AVX2
+            unsafe { super::avx2::gen_4_blocks(&self.state, out) };
+            self.advance(4);
+            return;
+        }
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if super::ssse3::is_available() {
+            // Safe because the CPU has been checked to support SSSE3
+            unsafe { super::ssse3::gen_4_blocks(&self.state, out) };
+            self.advance(4);
+            return;
+        }
+
+        #[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+        if super::neon::is_available() {
+            // Safe because the CPU has been checked to support NEON
+            unsafe { super::neon::gen_4_blocks(&self.state, out) };
+            self.advance(4);
+            return;
+        }
+
+        self.gen_4_blocks_scalar(out);
+    }
+
+    /// Generates `out.len()` consecutive blocks, four at a time through `gen_4_blocks` and the
+    /// remainder one by one. At least `out.len()` blocks must remain in the keystream.
+    #[inline]
+    pub(crate) fn gen_blocks(&mut self, out: &mut [Block]) {
+        let mut chunks = out.chunks_exact_mut(4);
+
+        for chunk in &mut chunks {
+            // Unwrap here is safe because the chunk is exactly four blocks long
+            self.gen_4_blocks(chunk.as_flattened_mut().try_into().unwrap());
+        }
+
+        for block in chunks.into_remainder() {
+            self.gen_block(block);
+        }
+    }
+
+    #[inline(always)]
+    fn gen_4_blocks_scalar(&mut self, out: &mut [u8; 4 * BLOCK_SIZE]) {
+        for chunk in out.chunks_exact_mut(BLOCK_SIZE) {
+            // Unwrap here is safe because the chunk is exactly one block long
+            self.gen_block(chunk.try_into().unwrap());
+        }
+    }
+
     #[inline(always)]
     pub(crate) fn full_round(&self) -> State {
+        let mut working_state = self.rounds();
+
+        working_state
+            .iter_mut()
+            .zip(&self.state)
+            .for_each(|(s1, s0)| *s1 = s1.wrapping_add(*s0));
+
+        working_state
+    }
+
+    /// Runs the 20 rounds over a copy of the state, without the final addition of the state
+    #[inline(always)]
+    pub(crate) fn rounds(&self) -> State {
         let mut working_state = self.state;
 
         // column round + diagonal round
@@ -84,17 +284,12 @@ impl ChaCha20Inner {
             quarter_round(&mut working_state, 3, 4, 9, 14);
         }
 
-        working_state
-            .iter_mut()
-            .zip(&self.state)
-            .for_each(|(s1, s0)| *s1 = s1.wrapping_add(*s0));
-
         working_state
     }
 }
 
 #[inline(always)]
-pub(self) fn quarter_round(state: &mut State, a: usize, b: usize, c: usize, d: usize) {
+fn quarter_round(state: &mut State, a: usize, b: usize, c: usize, d: usize) {
     state[a] = state[a].wrapping_add(state[b]);
     state[d] ^= state[a];
     state[d] = state[d].rotate_left(16);
@@ -154,4 +349,73 @@ mod test {
 
         assert_eq!(ctx.full_round(), expected_state);
     }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_state_layout() {
+        let key: Key = core::array::from_fn(|i| i as u8);
+        let nonce: Nonce = core::array::from_fn(|i| 0xa0 + i as u8);
+        let ctx = ChaCha20Inner::new_with_cnt(&key, &nonce, 0xdeadbeef);
+
+        // RFC 8439 section 2.3: constants, key, block counter, nonce
+        let expected_state: State = [
+            0x61707865, 0x3320646e, 0x79622d32, 0x6b206574,
+            0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c,
+            0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c,
+            0xdeadbeef, 0xa3a2a1a0, 0xa7a6a5a4, 0xabaaa9a8,
+        ];
+
+        assert_eq!(ctx.state, expected_state);
+    }
+
+    #[test]
+    fn block_serialization_byte_order() {
+        let key: Key = core::array::from_fn(|i| i as u8);
+        let nonce: Nonce = core::array::from_fn(|i| 0xa0 + i as u8);
+        let mut ctx = ChaCha20Inner::new_with_cnt(&key, &nonce, 7);
+
+        let words = ctx.full_round();
+        let mut block = [0; BLOCK_SIZE];
+        ctx.gen_block(&mut block);
+
+        // Each word is written least significant byte first, independently of the target
+        for (i, byte) in block.iter().enumerate() {
+            assert_eq!(*byte, (words[i / 4] >> (8 * (i % 4))) as u8);
+        }
+    }
+
+    /// Compares the keystream of a four-block backend with the scalar one over 1 MiB
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn assert_backend_matches_scalar(backend: unsafe fn(&State, &mut [u8; 4 * BLOCK_SIZE])) {
+        let key: Key = core::array::from_fn(|i| (i * 7) as u8);
+        let nonce: Nonce = core::array::from_fn(|i| (i * 13) as u8);
+        let mut scalar = ChaCha20Inner::new_with_cnt(&key, &nonce, 0);
+        let mut simd = scalar.clone();
+
+        let mut expected = [0u8; 4 * BLOCK_SIZE];
+        let mut out = [0u8; 4 * BLOCK_SIZE];
+
+        for _ in 0..(1 << 20) / (4 * BLOCK_SIZE) {
+            scalar.gen_4_blocks_scalar(&mut expected);
+            // Safe because the caller checked that the backend is available
+            unsafe { backend(&simd.state, &mut out) };
+            simd.advance(4);
+
+            assert_eq!(out, expected);
+        }
+
+        assert_eq!(simd.current_position(), scalar.current_position());
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn test_simd_backends() {
+        if crate::chacha20::avx2::is_available() {
+            assert_backend_matches_scalar(crate::chacha20::avx2::gen_4_blocks);
+        }
+
+        if crate::chacha20::ssse3::is_available() {
+            assert_backend_matches_scalar(crate::chacha20::ssse3::gen_4_blocks);
+        }
+    }
 }
@@ -0,0 +1,165 @@
+//! NEON backend generating four ChaCha20 blocks at once on aarch64.
+//!
+//! Each 128-bit register holds one row of a block, the four blocks are processed side by side
+//! so their independent instructions can be interleaved. The diagonal round is done by rotating
+//! the words of the rows before and after it.
+
+use core::arch::aarch64::*;
+
+use super::{BLOCK_SIZE, NUMBER_OF_ROUND, STATE_BLOCK_SIZE};
+
+// Runtime detection is always enabled in tests so the backend gets exercised
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+/// Returns whether the NEON backend can be used on the running CPU
+#[inline(always)]
+pub(crate) fn is_available() -> bool {
+    #[cfg(any(feature = "std", test))]
+    {
+        std::arch::is_aarch64_feature_detected!("neon")
+    }
+
+    #[cfg(not(any(feature = "std", test)))]
+    {
+        cfg!(target_feature = "neon")
+    }
+}
+
+/// Generates the four consecutive blocks starting at the counter of `state` into `out`.
+/// The counter of `state` is not advanced.
+///
+/// # Safety
+///
+/// The CPU must support NEON, see `is_available`.
+#[target_feature(enable = "neon")]
+pub(crate) unsafe fn gen_4_blocks(state: &[u32; STATE_BLOCK_SIZE], out: &mut [u8; 4 * BLOCK_SIZE]) {
+    let mut initial = [[vdupq_n_u32(0); 4]; 4];
+
+    for (i, rows) in initial.iter_mut().enumerate() {
+        let last_row = [
+            state[12].wrapping_add(i as u32),
+            state[13],
+            state[14],
+            state[15],
+        ];
+
+        *rows = [
+            vld1q_u32(state[0..4].as_ptr()),
+            vld1q_u32(state[4..8].as_ptr()),
+            vld1q_u32(state[8..12].as_ptr()),
+            vld1q_u32(last_row.as_ptr()),
+        ];
+    }
+
+    let mut x = initial;
+
+    for _ in 0..(NUMBER_OF_ROUND / 2) {
+        for rows in x.iter_mut() {
+            // column rounds
+            quarter_round(rows);
+
+            // diagonal rounds
+            diagonalize(rows);
+            quarter_round(rows);
+            undiagonalize(rows);
+        }
+    }
+
+    for ((rows, init), block) in x.iter().zip(&initial).zip(out.chunks_exact_mut(BLOCK_SIZE)) {
+        for ((row, init), chunk) in rows.iter().zip(init).zip(block.chunks_exact_mut(16)) {
+            // The words are stored little-endian, this backend is only built for such targets
+            vst1q_u8(
+                chunk.as_mut_ptr(),
+                vreinterpretq_u8_u32(vaddq_u32(*row, *init)),
+            );
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn quarter_round(x: &mut [uint32x4_t; 4]) {
+    let [mut a, mut b, mut c, mut d] = *x;
+
+    a = vaddq_u32(a, b);
+    d = rotate_left_16(veorq_u32(d, a));
+
+    c = vaddq_u32(c, d);
+    b = rotate_left::<12, 20>(veorq_u32(b, c));
+
+    a = vaddq_u32(a, b);
+    d = rotate_left::<8, 24>(veorq_u32(d, a));
+
+    c = vaddq_u32(c, d);
+    b = rotate_left::<7, 25>(veorq_u32(b, c));
+
+    *x = [a, b, c, d];
+}
+
+/// Rotates the words of the rows so the diagonals line up as columns
+#[inline(always)]
+unsafe fn diagonalize(x: &mut [uint32x4_t; 4]) {
+    x[1] = vextq_u32::<1>(x[1], x[1]);
+    x[2] = vextq_u32::<2>(x[2], x[2]);
+    x[3] = vextq_u32::<3>(x[3], x[3]);
+}
+
+#[inline(always)]
+unsafe fn undiagonalize(x: &mut [uint32x4_t; 4]) {
+    x[1] = vextq_u32::<3>(x[1], x[1]);
+    x[2] = vextq_u32::<2>(x[2], x[2]);
+    x[3] = vextq_u32::<1>(x[3], x[3]);
+}
+
+/// Rotates each word left by `L` bits, `R` must be `32 - L`
+#[inline(always)]
+unsafe fn rotate_left<const L: i32, const R: i32>(x: uint32x4_t) -> uint32x4_t {
+    vsriq_n_u32::<R>(vshlq_n_u32::<L>(x), x)
+}
+
+#[inline(always)]
+unsafe fn rotate_left_16(x: uint32x4_t) -> uint32x4_t {
+    vreinterpretq_u32_u16(vrev32q_u16(vreinterpretq_u16_u32(x)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chacha20::test::next_random;
+    use crate::chacha20::{ChaCha20, ChaCha20Inner};
+
+    #[test]
+    fn matches_scalar_backend() {
+        if !is_available() {
+            return;
+        }
+
+        let mut seed = 0x2545f4914f6cdd1d;
+        let mut data = [0u8; 16 * BLOCK_SIZE];
+        let mut expected = [0u8; 16 * BLOCK_SIZE];
+
+        for _ in 0..256 {
+            let mut key = [0u8; 32];
+            let mut nonce = [0u8; 12];
+            key.iter_mut()
+                .for_each(|v| *v = next_random(&mut seed) as u8);
+            nonce
+                .iter_mut()
+                .for_each(|v| *v = next_random(&mut seed) as u8);
+            let cnt = (next_random(&mut seed) as u32).min(u32::MAX - 16);
+            let len = next_random(&mut seed) as usize % data.len();
+
+            let mut scalar = ChaCha20Inner::new_with_cnt(&key, &nonce, cnt);
+            for chunk in expected.chunks_exact_mut(BLOCK_SIZE) {
+                scalar.gen_block(chunk.try_into().unwrap());
+            }
+
+            data.fill(0);
+            ChaCha20::new_with_cnt(&key, &nonce, cnt)
+                .perform_in_place(&mut data[..len])
+                .unwrap();
+
+            assert_eq!(data[..len], expected[..len]);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! SSSE3 backend generating four ChaCha20 blocks at once.
+//!
+//! Each 128-bit register holds one row of a block, the four blocks are processed side by side
+//! so their independent instructions can be interleaved. The diagonal round is done by rotating
+//! the words of the rows before and after it.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use super::{BLOCK_SIZE, NUMBER_OF_ROUND, STATE_BLOCK_SIZE};
+
+// Runtime detection is always enabled in tests so the backend gets exercised
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+/// Returns whether the SSSE3 backend can be used on the running CPU
+#[inline(always)]
+pub(crate) fn is_available() -> bool {
+    #[cfg(any(feature = "std", test))]
+    {
+        std::is_x86_feature_detected!("ssse3")
+    }
+
+    #[cfg(not(any(feature = "std", test)))]
+    {
+        cfg!(target_feature = "ssse3")
+    }
+}
+
+/// Generates the four consecutive blocks starting at the counter of `state` into `out`.
+/// The counter of `state` is not advanced.
+///
+/// # Safety
+///
+/// The CPU must support SSSE3, see `is_available`.
+#[target_feature(enable = "ssse3")]
+pub(crate) unsafe fn gen_4_blocks(state: &[u32; STATE_BLOCK_SIZE], out: &mut [u8; 4 * BLOCK_SIZE]) {
+    let s = state.map(|v| v as i32);
+    let mut initial = [[_mm_setzero_si128(); 4]; 4];
+
+    for (i, rows) in initial.iter_mut().enumerate() {
+        let cnt = state[12].wrapping_add(i as u32) as i32;
+
+        *rows = [
+            _mm_setr_epi32(s[0], s[1], s[2], s[3]),
+            _mm_setr_epi32(s[4], s[5], s[6], s[7]),
+            _mm_setr_epi32(s[8], s[9], s[10], s[11]),
+            _mm_setr_epi32(cnt, s[13], s[14], s[15]),
+        ];
+    }
+
+    let mut x = initial;
+
+    for _ in 0..(NUMBER_OF_ROUND / 2) {
+        for rows in x.iter_mut() {
+            // column rounds
+            quarter_round(rows);
+
+            // diagonal rounds
+            diagonalize(rows);
+            quarter_round(rows);
+            undiagonalize(rows);
+        }
+    }
+
+    for ((rows, init), block) in x.iter().zip(&initial).zip(out.chunks_exact_mut(BLOCK_SIZE)) {
+        for ((row, init), chunk) in rows.iter().zip(init).zip(block.chunks_exact_mut(16)) {
+            _mm_storeu_si128(
+                chunk.as_mut_ptr() as *mut __m128i,
+                _mm_add_epi32(*row, *init),
+            );
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn quarter_round(x: &mut [__m128i; 4]) {
+    let [mut a, mut b, mut c, mut d] = *x;
+
+    a = _mm_add_epi32(a, b);
+    d = rotate_left_16(_mm_xor_si128(d, a));
+
+    c = _mm_add_epi32(c, d);
+    b = rotate_left::<12, 20>(_mm_xor_si128(b, c));
+
+    a = _mm_add_epi32(a, b);
+    d = rotate_left_8(_mm_xor_si128(d, a));
+
+    c = _mm_add_epi32(c, d);
+    b = rotate_left::<7, 25>(_mm_xor_si128(b, c));
+
+    *x = [a, b, c, d];
+}
+
+/// Rotates the words of the rows so the diagonals line up as columns
+#[inline(always)]
+unsafe fn diagonalize(x: &mut [__m128i; 4]) {
+    x[1] = _mm_shuffle_epi32(x[1], 0b00_11_10_01);
+    x[2] = _mm_shuffle_epi32(x[2], 0b01_00_11_10);
+    x[3] = _mm_shuffle_epi32(x[3], 0b10_01_00_11);
+}
+
+#[inline(always)]
+unsafe fn undiagonalize(x: &mut [__m128i; 4]) {
+    x[1] = _mm_shuffle_epi32(x[1], 0b10_01_00_11);
+    x[2] = _mm_shuffle_epi32(x[2], 0b01_00_11_10);
+    x[3] = _mm_shuffle_epi32(x[3], 0b00_11_10_01);
+}
+
+#[inline(always)]
+unsafe fn rotate_left<const L: i32, const R: i32>(x: __m128i) -> __m128i {
+    _mm_or_si128(_mm_slli_epi32::<L>(x), _mm_srli_epi32::<R>(x))
+}
+
+#[inline(always)]
+unsafe fn rotate_left_16(x: __m128i) -> __m128i {
+    _mm_shuffle_epi8(
+        x,
+        _mm_setr_epi8(2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9, 14, 15, 12, 13),
+    )
+}
+
+#[inline(always)]
+unsafe fn rotate_left_8(x: __m128i) -> __m128i {
+    _mm_shuffle_epi8(
+        x,
+        _mm_setr_epi8(3, 0, 1, 2, 7, 4, 5, 6, 11, 8, 9, 10, 15, 12, 13, 14),
+    )
+}
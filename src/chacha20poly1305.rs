@@ -3,215 +3,3170 @@
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
+use crate::error::DecryptError;
 use crate::*;
-use zeroize::Zeroize as _;
+#[cfg(all(feature = "alloc", feature = "subtle"))]
+use subtle::{Choice, ConditionallySelectable as _, ConstantTimeEq as _};
+use zeroize::{Zeroize as _, ZeroizeOnDrop};
+
+#[cfg(feature = "aead")]
+mod aead_traits;
+#[cfg(feature = "alloc")]
+pub mod stream;
 
 const DATA_CHUNK_SIZE: usize = 16 * 4;
+const TAG_SIZE: usize = core::mem::size_of::<Tag>();
+
+/// Largest plaintext in bytes a single message can hold, the `2^32 - 1` keystream blocks left after
+/// the block 0 used for the Poly1305 key.
+pub const MAX_PLAINTEXT_LEN: u64 = u32::MAX as u64 * 64;
+/// Largest AAD in bytes a single message can hold, as its length is encoded on 64 bits.
+pub const MAX_AAD_LEN: u64 = u64::MAX;
+
+/// Size of the `nonce || ciphertext length` header of `seal_framed`
+#[cfg(feature = "alloc")]
+const FRAME_HEADER_SIZE: usize = 12 + 4;
+
+/// Domain separation input of the HChaCha20 calls deriving key fingerprints
+const FINGERPRINT_DOMAIN: [u8; 16] = *b"sco-fingerprint!";
+
+/// Fixed, public Poly1305 key used to derive the content IDs of sealed messages
+#[cfg(feature = "alloc")]
+const CONTENT_ID_KEY: Poly1305Key = *b"chacha20poly1305-content-id-key!";
 
-/// Represents the ChaCha20-Poly1305 AEAD cipher state.
+/// Number of nonces remembered by the `debug-nonce-checks` feature.
+#[cfg(feature = "debug-nonce-checks")]
+pub const NONCE_LOG_SIZE: usize = 64;
+
+/// Represents the ChaCha20-Poly1305 AEAD cipher, keyed once and used with a fresh nonce per message.
+///
+/// With the `debug-nonce-checks` feature, the instance remembers the last `NONCE_LOG_SIZE` nonces
+/// it sealed and panics when one of them is used again. With `alloc` as well, it also keeps a
+/// fingerprint of every one-time Poly1305 key it derived for its whole lifetime, and debug
+/// assertions catch a repeated key even once its nonce left the log. The logs make the instance
+/// `!Sync`.
 pub struct ChaCha20Poly1305 {
-    pub(crate) chacha20: ChaCha20,
-    pub(crate) poly1305: Poly1305,
-    data_len: u64,
-    aad_len: u64,
+    key: Key,
+    mac_key: Option<Poly1305Key>,
+    #[cfg(feature = "debug-nonce-checks")]
+    nonce_log: core::cell::RefCell<NonceLog>,
+    #[cfg(all(feature = "debug-nonce-checks", feature = "alloc"))]
+    one_time_keys: core::cell::RefCell<alloc::collections::BTreeSet<[u8; 8]>>,
+}
+
+impl Drop for ChaCha20Poly1305 {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        if let Some(mac_key) = &mut self.mac_key {
+            mac_key.zeroize();
+        }
+    }
 }
 
+impl ZeroizeOnDrop for ChaCha20Poly1305 {}
+
 impl ChaCha20Poly1305 {
-    /// Creates a new ChaCha20Poly1305 instance with the provided key, nonce, and additional authenticated data (AAD).
-    pub fn new(key: &Key, nonce: &Nonce, aad: &[u8]) -> Result<Self> {
-        let mut chacha20 = ChaCha20::new_with_cnt(key, nonce, 1);
-        let mut poly1305_key = gen_poly1305_key(&mut chacha20);
-        let mut poly1305 = Poly1305::new(&poly1305_key);
+    /// Size of the key in bytes
+    pub const KEY_SIZE: usize = core::mem::size_of::<Key>();
+    /// Size of the nonce in bytes
+    pub const NONCE_SIZE: usize = core::mem::size_of::<Nonce>();
+    /// Size of the tag in bytes
+    pub const TAG_SIZE: usize = core::mem::size_of::<Tag>();
 
-        poly1305_key.zeroize();
+    /// Creates a new ChaCha20Poly1305 instance with the provided key.
+    ///
+    /// The key is copied in and kept for the lifetime of the instance, every message then only
+    /// needs its nonce, from which the one-time Poly1305 key is derived with the keystream block 0.
+    /// The stored key is zeroized when the instance is dropped.
+    pub const fn new(key: &Key) -> Self {
+        Self {
+            key: *key,
+            mac_key: None,
+            #[cfg(feature = "debug-nonce-checks")]
+            nonce_log: core::cell::RefCell::new(NonceLog::new()),
+            #[cfg(all(feature = "debug-nonce-checks", feature = "alloc"))]
+            one_time_keys: core::cell::RefCell::new(alloc::collections::BTreeSet::new()),
+        }
+    }
 
-        poly1305.update(&aad);
-        poly1305.update_leftover_pad16();
+    /// Creates a ChaCha20Poly1305 instance with separate encryption and MAC keys, like the
+    /// constructions predating RFC 8439.
+    ///
+    /// This is a non-RFC variant: `mac_key` is used directly as the Poly1305 key of every message
+    /// instead of being derived from the keystream block 0, and the encryption starts at the
+    /// counter 0. Its output is not interoperable with the standard AEAD. Since the Poly1305 key is
+    /// no longer one-time, the tags of messages sealed under the same `mac_key` are not
+    /// independent, so this should only be used where a peer requires it.
+    pub const fn with_split_keys(enc_key: &Key, mac_key: &Poly1305Key) -> Self {
+        Self {
+            key: *enc_key,
+            mac_key: Some(*mac_key),
+            #[cfg(feature = "debug-nonce-checks")]
+            nonce_log: core::cell::RefCell::new(NonceLog::new()),
+            #[cfg(all(feature = "debug-nonce-checks", feature = "alloc"))]
+            one_time_keys: core::cell::RefCell::new(alloc::collections::BTreeSet::new()),
+        }
+    }
 
-        Ok(Self {
-            chacha20,
-            poly1305,
-            data_len: 0,
-            aad_len: u64::try_from(aad.len()).map_err(|_| error::Error::AadTooLong)?,
-        })
+    /// Creates the cipher of a session, keyed with a subkey derived from `master` and `session_id`
+    /// with HChaCha20, so every session has its own key.
+    ///
+    /// The session ID is zero-padded to the 16 bytes of the HChaCha20 input, so IDs differing only
+    /// by trailing zeros derive the same key, a protocol should use IDs of a fixed length. Returns
+    /// `Error::InvalidLength` if the session ID is longer than 16 bytes.
+    pub fn derive_session(master: &Key, session_id: &[u8]) -> Result<Self> {
+        let mut input = [0; 16];
+        input
+            .get_mut(..session_id.len())
+            .ok_or(error::Error::InvalidLength)?
+            .copy_from_slice(session_id);
+
+        let mut session_key = chacha20::hchacha20(master, &input);
+        let cipher = Self::new(&session_key);
+
+        session_key.zeroize();
+        input.zeroize();
+        Ok(cipher)
     }
 
+    #[cfg(feature = "rand_core")]
     #[inline]
-    /// Encrypts the provided data in-place.
-    pub fn encrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
-        self.chacha20.perform_in_place(data)?;
-        self.poly1305.update(data);
-        self.data_len += u64::try_from(data.len()).unwrap();
-        Ok(())
+    /// Generates a random key for the AEAD cipher from the provided RNG.
+    pub fn generate_key(rng: &mut impl rand_core::RngCore) -> Key {
+        ChaCha20::generate_key(rng)
     }
 
-    #[cfg(feature = "alloc")]
-    #[inline(always)]
-    /// Encrypts the provided data and returns the result as a new vector.
-    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
-        let mut data = try_to_vec(data)?;
-        self.encrypt_in_place(&mut data)?;
-        Ok(data)
+    #[cfg(feature = "rand_core")]
+    #[inline]
+    /// Generates a random nonce from the provided RNG. With random nonces, a key should not seal
+    /// more than about 2^32 messages to keep the probability of a collision negligible.
+    pub fn generate_nonce(rng: &mut impl rand_core::RngCore) -> Nonce {
+        ChaCha20::generate_nonce(rng)
     }
 
     #[inline]
-    /// Decrypts the provided data in-place.
-    pub fn decrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
-        self.poly1305.update(data);
-        self.chacha20.perform_in_place(data)?;
-        self.data_len += u64::try_from(data.len()).unwrap();
-        Ok(())
+    /// Starts encrypting a single message incrementally, with the AAD supplied through
+    /// `StreamEncryptor::update_aad` before the plaintext.
+    pub fn encryptor(&self, nonce: &Nonce) -> StreamEncryptor {
+        self.track_nonce(nonce);
+        StreamEncryptor {
+            // Unwrap here is safe because the empty AAD cannot be too long
+            state: self.start(nonce, &[]).unwrap(),
+            aad_finished: false,
+        }
     }
 
     #[cfg(feature = "alloc")]
-    #[inline(always)]
-    /// Decrypts the provided data and returns the result as a new vector.
-    pub fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
-        let mut data = try_to_vec(data)?;
-        self.decrypt_in_place(&mut data)?;
-        Ok(data)
+    /// Starts encrypting a single message pushed piece by piece into a `StreamingSealer`, which
+    /// hands out the ciphertext of each piece before the next one is pushed.
+    pub fn sealer(&self, nonce: &Nonce, aad: &[u8]) -> Result<StreamingSealer> {
+        self.track_nonce(nonce);
+        Ok(StreamingSealer {
+            state: self.start(nonce, aad)?,
+            buffer: zeroize::Zeroizing::new(Vec::new()),
+            emitted: 0,
+        })
     }
 
-    #[inline]
-    fn auth_len(&mut self) {
-        self.poly1305.update(&self.aad_len.to_le_bytes());
-        self.poly1305.update(&self.data_len.to_le_bytes());
+    #[cfg(feature = "alloc")]
+    /// Starts decrypting a single message fed piece by piece into a `StreamingOpener`, for
+    /// ciphertexts whose length is not known in advance.
+    pub fn opener(&self, nonce: &Nonce, aad: &[u8]) -> Result<StreamingOpener> {
+        Ok(StreamingOpener {
+            state: self.start(nonce, aad)?,
+            buffer: zeroize::Zeroizing::new(Vec::new()),
+            tail: [0; TAG_SIZE],
+            tail_len: 0,
+        })
+    }
+
+    /// Returns the ChaCha20 cipher encrypting the record at `record_index` of a session, positioned
+    /// at counter 1 since counter 0 is reserved for the Poly1305 key.
+    ///
+    /// The nonce of the record is `base_nonce` XORed with the big-endian `record_index` in its last
+    /// 8 bytes, like the per-record nonces of TLS 1.3.
+    /// With split keys, the cipher is positioned at counter 0 instead.
+    pub fn cipher_for_record(&self, base_nonce: &Nonce, record_index: u64) -> Result<ChaCha20> {
+        Ok(ChaCha20::new_with_cnt(
+            &self.key,
+            &record_nonce(base_nonce, record_index),
+            self.first_data_block(),
+        ))
+    }
+
+    /// Returns the one-time Poly1305 key the AEAD derives for the message with `nonce`, the first
+    /// 32 bytes of the keystream block 0, see RFC 8439 section 2.6.
+    ///
+    /// Meant for building other constructions on top of the AEAD or checking intermediate values,
+    /// the key must only ever authenticate a single message.
+    pub fn poly1305_key(key: &Key, nonce: &Nonce) -> Poly1305Key {
+        gen_poly1305_key(&mut ChaCha20::new(key, nonce))
+    }
+
+    /// Computes the AEAD tag of a ciphertext encrypted elsewhere, e.g. by hardware, without
+    /// touching the data itself: the one-time key derivation, the AAD, the ciphertext and the
+    /// length block are authenticated exactly like `encrypt_detached` does.
+    ///
+    /// A received tag must be compared with the result with `poly1305::verify_tag`, never with
+    /// `==`.
+    pub fn compute_tag(key: &Key, nonce: &Nonce, aad: &[u8], ciphertext: &[u8]) -> Tag {
+        let mut poly1305_key = Self::poly1305_key(key, nonce);
+        let mut poly1305 = Poly1305::new(&poly1305_key);
+        poly1305_key.zeroize();
+
+        poly1305.update(aad);
+        poly1305.update_leftover_pad16();
+        poly1305.update(ciphertext);
+        poly1305.update_leftover_pad16();
+        poly1305.update(&(aad.len() as u64).to_le_bytes());
+        poly1305.update(&(ciphertext.len() as u64).to_le_bytes());
+        poly1305.finalize()
+    }
+
+    /// Returns a short fingerprint identifying the key, meant for logging.
+    ///
+    /// The fingerprint is the first 8 bytes of HChaCha20 keyed with a subkey, itself derived from
+    /// the key with HChaCha20, over a fixed domain separation input. A single HChaCha20 output is
+    /// the working state of a ChaCha20 block, so the second call keeps the fingerprint unrelated to
+    /// the keystream and the one-time Poly1305 keys of every nonce. It does not reveal the key, but
+    /// it is not the key either and cannot be used in its place.
+    pub fn key_fingerprint(&self) -> [u8; 8] {
+        let mut subkey = chacha20::hchacha20(&self.key, &FINGERPRINT_DOMAIN);
+        let mut digest = chacha20::hchacha20(&subkey, &FINGERPRINT_DOMAIN);
+
+        // Unwrap here is safe because the HChaCha20 output is longer than 8 bytes
+        let fingerprint = digest[..8].try_into().unwrap();
+        subkey.zeroize();
+        digest.zeroize();
+        fingerprint
     }
 
     #[inline]
-    /// Get the `Tag` of the processed data
-    pub fn finalize(mut self) -> Tag {
-        self.poly1305.update_leftover_pad16();
-        self.auth_len();
-        self.poly1305.finalize()
+    /// Starts processing a single message with the provided nonce and additional authenticated data (AAD).
+    pub fn start(&self, nonce: &Nonce, aad: &[u8]) -> Result<AeadState> {
+        self.with_mac(nonce, aad)
+    }
+
+    /// Starts processing a single message like `start`, authenticated by `M` instead of Poly1305.
+    /// Only the tests use another authenticator, to observe what the AEAD feeds to it.
+    fn with_mac<M: Mac>(&self, nonce: &Nonce, aad: &[u8]) -> Result<AeadState<M>> {
+        match &self.mac_key {
+            Some(mac_key) => AeadState::with_mac_key(ChaCha20::new(&self.key, nonce), mac_key, aad),
+            None => AeadState::new_with_mac(&self.key, nonce, aad),
+        }
+    }
+
+    #[cfg(feature = "debug-nonce-checks")]
+    /// Records the nonce of a message about to be sealed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the nonce is among the last `NONCE_LOG_SIZE` nonces sealed by this instance.
+    /// With `alloc`, the debug assertions also fail if the one-time Poly1305 key of the nonce has
+    /// already been derived by this instance.
+    fn track_nonce(&self, nonce: &Nonce) {
+        if !self.nonce_log.borrow_mut().insert(nonce) {
+            panic!("nonce reused with the same ChaCha20Poly1305 instance");
+        }
+
+        // Split keys have no one-time key to track
+        #[cfg(feature = "alloc")]
+        if self.mac_key.is_none() {
+            let mut one_time_key = gen_poly1305_key(&mut ChaCha20::new(&self.key, nonce));
+            let mut fingerprint = chacha20::hchacha20(&one_time_key, &[0; 16]);
+            one_time_key.zeroize();
+
+            // Unwrap here is safe because the HChaCha20 output is longer than 8 bytes
+            let fresh = self
+                .one_time_keys
+                .borrow_mut()
+                .insert(fingerprint[..8].try_into().unwrap());
+            fingerprint.zeroize();
+
+            debug_assert!(
+                fresh,
+                "one-time Poly1305 key reused with the same ChaCha20Poly1305 instance"
+            );
+        }
     }
 
+    #[cfg(not(feature = "debug-nonce-checks"))]
+    #[inline(always)]
+    fn track_nonce(&self, _nonce: &Nonce) {}
+
+    /// Returns the counter of the first keystream block encrypting the data.
     #[inline]
-    /// Verify the `Tag` with the processed
-    pub fn verify(mut self, tag: &Tag) -> Result<()> {
-        self.data_len = u64::try_from(self.data_len).unwrap();
-        self.poly1305.update_leftover_pad16();
-        self.auth_len();
+    fn first_data_block(&self) -> u32 {
+        match self.mac_key {
+            Some(_) => 0,
+            None => 1,
+        }
+    }
 
-        if !self.poly1305.verify(tag) {
-            Err(error::Error::Unauthenticated)
-        } else {
-            Ok(())
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext and returns the ciphertext with the authentication tag appended.
+    ///
+    /// Returns `Error::DataTooLong` if the plaintext is longer than `MAX_PLAINTEXT_LEN`, or
+    /// `Error::AadTooLong` if the AAD is longer than `MAX_AAD_LEN`, before doing any work.
+    pub fn encrypt(&self, nonce: &Nonce, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let (mut ciphertext, tag) = self.encrypt_detached(nonce, aad, plaintext)?;
+
+        ciphertext
+            .try_reserve(TAG_SIZE)
+            .map_err(|_| error::Error::OutOfMemory)?;
+        ciphertext.extend_from_slice(&tag);
+
+        Ok(ciphertext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Decrypts `ct_with_tag` (ciphertext followed by the 16 bytes tag) and returns the plaintext.
+    /// `aad` and `ct_with_tag` are only read, so they may be sub-slices of the same buffer.
+    ///
+    /// Returns `DecryptError::Unauthenticated` if `ct_with_tag` is shorter than the 16 bytes tag
+    /// or if the tag does not match.
+    pub fn decrypt(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        ct_with_tag: &[u8],
+    ) -> core::result::Result<Vec<u8>, DecryptError> {
+        let split_at = ct_with_tag
+            .len()
+            .checked_sub(TAG_SIZE)
+            .ok_or(DecryptError::Unauthenticated)?;
+
+        let (ciphertext, tag) = ct_with_tag.split_at(split_at);
+
+        // Unwrap here is safe because the tag slice is exactly TAG_SIZE bytes long
+        self.decrypt_detached(nonce, aad, ciphertext, tag.try_into().unwrap())
+    }
+
+    /// Encrypts the buffer in-place and returns the authentication tag, without any allocation.
+    pub fn encrypt_in_place_detached(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<Tag> {
+        check_lengths(aad.len() as u128, buffer.len() as u128)?;
+        self.track_nonce(nonce);
+        self.start(nonce, aad)?.encrypt_oneshot_in_place(buffer)
+    }
+
+    /// Returns the number of bytes the sealed form of a `plaintext_len` bytes long plaintext takes,
+    /// the ciphertext followed by the tag, so the memory can be reserved before encrypting.
+    ///
+    /// Returns `Error::DataTooLong` if the size does not fit in a `usize`.
+    pub const fn encrypt_alloc_size(plaintext_len: usize) -> Result<usize> {
+        match plaintext_len.checked_add(TAG_SIZE) {
+            Some(size) => Ok(size),
+            None => Err(error::Error::DataTooLong),
         }
     }
-    /// Encrypts the provided data in-place in a one-shot operation and returns the authentication tag.
-    pub fn encrypt_oneshot_in_place(mut self, data: &mut [u8]) -> Result<Tag> {
-        for chunk in data.chunks_mut(DATA_CHUNK_SIZE) {
-            self.chacha20.perform_in_place(chunk)?;
-            self.poly1305.update(chunk);
+
+    /// Returns the number of bytes the plaintext of a `ciphertext_len` bytes long sealed message
+    /// (ciphertext followed by the tag) takes, so the memory can be reserved before decrypting.
+    ///
+    /// Returns `Error::Unauthenticated` if the input is shorter than the tag, as no valid message
+    /// can be that short.
+    pub const fn decrypt_alloc_size(ciphertext_len: usize) -> Result<usize> {
+        match ciphertext_len.checked_sub(TAG_SIZE) {
+            Some(size) => Ok(size),
+            None => Err(error::Error::Unauthenticated),
         }
+    }
 
-        self.data_len = u64::try_from(data.len()).unwrap();
+    #[cfg(feature = "alloc")]
+    /// Decrypts `ct_with_tag`, seals the plaintext again with the same nonce and returns whether
+    /// the result matches the input byte for byte, as a defense in depth check for storage
+    /// integrity tools.
+    ///
+    /// Corrupted data is rejected by the decryption like with `decrypt`, and reported as
+    /// `Error::Unauthenticated`. A message accepted by `decrypt` always seals back to itself, so
+    /// `false` points at a fault of the machine rather than of the data.
+    pub fn verify_roundtrip(&self, nonce: &Nonce, aad: &[u8], ct_with_tag: &[u8]) -> Result<bool> {
+        let plaintext = zeroize::Zeroizing::new(self.decrypt(nonce, aad, ct_with_tag)?);
 
-        Ok(self.finalize())
+        // Sealing the same plaintext under the same nonce reveals nothing new, so this bypasses
+        // the nonce reuse checks
+        let (ciphertext, tag) = self.start(nonce, aad)?.encrypt_oneshot(&plaintext)?;
+        let (expected_ciphertext, expected_tag) = ct_with_tag.split_at(ciphertext.len());
+
+        Ok(ciphertext[..] == *expected_ciphertext && tag[..] == *expected_tag)
+    }
+
+    /// Encrypts the plaintext into `out` as the ciphertext followed by the tag, without any
+    /// allocation, and returns the number of bytes written.
+    ///
+    /// Returns `Error::InvalidLength` if `out` is shorter than the plaintext plus the 16 bytes tag.
+    pub fn encrypt_to_slice(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        plaintext: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize> {
+        let len = plaintext.len();
+        if out.len() < len + TAG_SIZE {
+            return Err(error::Error::InvalidLength);
+        }
+
+        let (ciphertext, rest) = out.split_at_mut(len);
+        ciphertext.copy_from_slice(plaintext);
+
+        let tag = self.encrypt_in_place_detached(nonce, aad, ciphertext)?;
+        rest[..TAG_SIZE].copy_from_slice(&tag);
+
+        Ok(len + TAG_SIZE)
+    }
+
+    /// Verifies `ct_with_tag` (ciphertext followed by the 16 bytes tag) and decrypts it into `out`
+    /// without any allocation, returning the length of the plaintext.
+    ///
+    /// Returns `Error::InvalidLength` if `out` is shorter than the ciphertext, and
+    /// `Error::Unauthenticated` if the input is shorter than the tag or the tag does not match, in
+    /// which case `out` only holds the ciphertext.
+    pub fn decrypt_to_slice(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        ct_with_tag: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize> {
+        let len = ct_with_tag
+            .len()
+            .checked_sub(TAG_SIZE)
+            .ok_or(error::Error::Unauthenticated)?;
+
+        if out.len() < len {
+            return Err(error::Error::InvalidLength);
+        }
+
+        let (ciphertext, tag) = ct_with_tag.split_at(len);
+        out[..len].copy_from_slice(ciphertext);
+
+        // Unwrap here is safe because the tag slice is exactly TAG_SIZE bytes long
+        self.decrypt_in_place_detached(nonce, aad, &mut out[..len], tag.try_into().unwrap())?;
+        Ok(len)
+    }
+
+    /// Decrypts the buffer in-place after verifying the detached authentication tag, without any
+    /// allocation. Returns `DecryptError::Unauthenticated` before decrypting anything if the tag is invalid.
+    pub fn decrypt_in_place_detached(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &Tag,
+    ) -> core::result::Result<(), DecryptError> {
+        self.start(nonce, aad)
+            .and_then(|state| state.decrypt_oneshot_in_place(buffer, tag))
+            .map_err(error::Error::into_decrypt_error)
     }
 
     #[cfg(feature = "alloc")]
-    /// Encrypts the provided data in a one-shot operation and returns the result as a new vector along with the authentication tag.
-    pub fn encrypt_oneshot(self, data: &[u8]) -> Result<(Vec<u8>, Tag)> {
-        let mut data = try_to_vec(data)?;
-        let tag = self.encrypt_oneshot_in_place(&mut data)?;
-        Ok((data, tag))
+    /// Decrypts `ct_with_tag` like `decrypt`, trying the key of this cipher first and then each of
+    /// `keys` in order, for a key rotation grace period where messages may still be sealed under
+    /// a previous key. Returns the plaintext under the first key the tag verifies with.
+    ///
+    /// Each tag comparison is constant time, but the number of keys tried reveals which key
+    /// matched through timing.
+    pub fn open_any(
+        &self,
+        keys: &[Key],
+        nonce: &Nonce,
+        aad: &[u8],
+        ct_with_tag: &[u8],
+    ) -> core::result::Result<Vec<u8>, DecryptError> {
+        let split_at = ct_with_tag
+            .len()
+            .checked_sub(TAG_SIZE)
+            .ok_or(DecryptError::Unauthenticated)?;
+
+        let (ciphertext, tag) = ct_with_tag.split_at(split_at);
+        // Unwrap here is safe because the tag slice is exactly TAG_SIZE bytes long
+        let tag: &Tag = tag.try_into().unwrap();
+
+        let states = core::iter::once(self.start(nonce, aad))
+            .chain(keys.iter().map(|key| AeadState::new(key, nonce, aad)));
+
+        for state in states {
+            match state.and_then(|s| s.decrypt_oneshot(ciphertext, tag)) {
+                Ok(plaintext) => return Ok(plaintext),
+                Err(error::Error::Unauthenticated) => continue,
+                Err(err) => return Err(err.into_decrypt_error()),
+            }
+        }
+
+        Err(DecryptError::Unauthenticated)
     }
 
-    /// Decrypts the provided data in-place in a one-shot operation and verifies the authentication tag.
-    pub fn decrypt_oneshot_in_place(mut self, data: &mut [u8], tag: &Tag) -> Result<()> {
-        for chunk in data.chunks_mut(DATA_CHUNK_SIZE) {
-            self.poly1305.update(chunk);
-            self.chacha20.perform_in_place(chunk)?;
+    #[cfg(feature = "getrandom")]
+    /// Encrypts the plaintext under a random nonce drawn from the system randomness, and returns
+    /// `nonce || ciphertext || tag` to be opened with `open`.
+    ///
+    /// With random nonces, a key should not seal more than about 2^32 messages to keep the
+    /// probability of a nonce collision negligible. Returns `Error::RandomnessUnavailable` if the
+    /// system randomness cannot be read.
+    pub fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce: Nonce = [0; 12];
+        getrandom::getrandom(&mut nonce).map_err(|_| error::Error::RandomnessUnavailable)?;
+
+        let (ciphertext, tag) = self.encrypt_detached(&nonce, aad, plaintext)?;
+
+        let mut sealed = Vec::new();
+        sealed
+            .try_reserve(nonce.len() + ciphertext.len() + TAG_SIZE)
+            .map_err(|_| error::Error::OutOfMemory)?;
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed.extend_from_slice(&tag);
+
+        Ok(sealed)
+    }
+
+    #[cfg(feature = "getrandom")]
+    /// Decrypts a message produced by `seal`, taking the nonce from its first 12 bytes.
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> core::result::Result<Vec<u8>, DecryptError> {
+        let Some((nonce, ct_with_tag)) = sealed.split_first_chunk::<12>() else {
+            return Err(DecryptError::Unauthenticated);
+        };
+
+        self.decrypt(nonce, aad, ct_with_tag)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext into a self-describing frame
+    /// `nonce (12) || ciphertext length (u32 LE) || ciphertext || tag (16)`, to be opened with
+    /// `open_framed`. The header is sent in the clear and is not authenticated itself, a forged
+    /// nonce or length fails the tag verification instead.
+    ///
+    /// Returns `Error::DataTooLong` if the plaintext length does not fit into 32 bits.
+    pub fn seal_framed(&self, nonce: &Nonce, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let ct_len = u32::try_from(plaintext.len()).map_err(|_| error::Error::DataTooLong)?;
+        let (ciphertext, tag) = self.encrypt_detached(nonce, aad, plaintext)?;
+
+        let mut frame = Vec::new();
+        frame
+            .try_reserve(FRAME_HEADER_SIZE + ciphertext.len() + TAG_SIZE)
+            .map_err(|_| error::Error::OutOfMemory)?;
+        frame.extend_from_slice(nonce);
+        frame.extend_from_slice(&ct_len.to_le_bytes());
+        frame.extend_from_slice(&ciphertext);
+        frame.extend_from_slice(&tag);
+
+        Ok(frame)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Parses a frame produced by `seal_framed` and decrypts it.
+    ///
+    /// Returns `Error::MalformedFrame` if the frame is shorter than its header and tag or if its
+    /// length does not match the ciphertext length of the header, and `Error::Unauthenticated` if
+    /// the tag does not match.
+    pub fn open_framed(&self, aad: &[u8], frame: &[u8]) -> Result<Vec<u8>> {
+        if frame.len() < FRAME_HEADER_SIZE + TAG_SIZE {
+            return Err(error::Error::MalformedFrame);
         }
 
-        self.data_len = u64::try_from(data.len()).unwrap();
-        self.poly1305.update_leftover_pad16();
-        self.auth_len();
+        let (header, ct_with_tag) = frame.split_at(FRAME_HEADER_SIZE);
+        // Unwrap here is safe because the header is exactly FRAME_HEADER_SIZE bytes long
+        let nonce: Nonce = header[..12].try_into().unwrap();
+        let ct_len = u32::from_le_bytes(header[12..].try_into().unwrap());
 
-        if !self.poly1305.verify(tag) {
-            return Err(error::Error::Unauthenticated);
+        if u64::from(ct_len) != (ct_with_tag.len() - TAG_SIZE) as u64 {
+            return Err(error::Error::MalformedFrame);
         }
 
-        Ok(())
+        Ok(self.decrypt(&nonce, aad, ct_with_tag)?)
     }
 
     #[cfg(feature = "alloc")]
-    /// Decrypts the provided data in a one-shot operation, verifying the authentication tag, and returns the result as a new vector.
-    pub fn decrypt_oneshot(self, data: &[u8], tag: &Tag) -> Result<Vec<u8>> {
-        let mut data = try_to_vec(data)?;
-        self.decrypt_oneshot_in_place(&mut data, tag)?;
+    /// Encrypts the plaintext like `encrypt`, deriving the one-time Poly1305 key in the
+    /// caller-provided `scratch` block instead of on the stack.
+    ///
+    /// The whole keystream block 0 is written to `scratch`, and it is zeroized before returning.
+    /// With split keys, there is no key to derive and `scratch` is left untouched.
+    pub fn encrypt_with_scratch(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        plaintext: &[u8],
+        scratch: &mut [u8; 64],
+    ) -> Result<Vec<u8>> {
+        check_lengths(aad.len() as u128, plaintext.len() as u128)?;
+
+        if self.mac_key.is_some() {
+            return self.encrypt(nonce, aad, plaintext);
+        }
+
+        self.track_nonce(nonce);
+        let mut chacha20 = ChaCha20::new(&self.key, nonce);
+        chacha20.fill_keystream(scratch)?;
+
+        // Unwrap here is safe because the scratch block is longer than a Poly1305 key
+        let state =
+            AeadState::<Poly1305>::with_mac_key(chacha20, scratch[..32].try_into().unwrap(), aad);
+        scratch.zeroize();
+
+        let (mut ciphertext, tag) = state?.encrypt_oneshot(plaintext)?;
+        ciphertext
+            .try_reserve(TAG_SIZE)
+            .map_err(|_| error::Error::OutOfMemory)?;
+        ciphertext.extend_from_slice(&tag);
+
+        Ok(ciphertext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext and returns the ciphertext and the authentication tag separately.
+    pub fn encrypt_detached(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, Tag)> {
+        check_lengths(aad.len() as u128, plaintext.len() as u128)?;
+        self.track_nonce(nonce);
+        self.start(nonce, aad)?.encrypt_oneshot(plaintext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Decrypts the ciphertext, verifying the detached authentication tag, and returns the plaintext.
+    /// Returns `DecryptError::Unauthenticated` without allocating the plaintext if the tag is
+    /// invalid, so unauthenticated plaintext is never handed out.
+    pub fn decrypt_detached(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        ciphertext: &[u8],
+        tag: &Tag,
+    ) -> core::result::Result<Vec<u8>, DecryptError> {
+        self.start(nonce, aad)
+            .and_then(|state| state.decrypt_oneshot(ciphertext, tag))
+            .map_err(error::Error::into_decrypt_error)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Authenticates the AAD and the plaintext, appends the tag to the plaintext and encrypts both
+    /// (MAC-then-encrypt). The tag is computed with the same one-time key, padding and length block
+    /// as the AEAD, but over the plaintext instead of the ciphertext.
+    ///
+    /// # Security
+    ///
+    /// This ordering is weaker than the standard encrypt-then-MAC construction: the receiver has to
+    /// decrypt before it can authenticate, which exposes it to padding-oracle style attacks and to
+    /// processing unauthenticated plaintext. It is only provided to interoperate with legacy peers,
+    /// prefer `encrypt` everywhere else.
+    pub fn mac_then_encrypt(&self, nonce: &Nonce, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        check_lengths(aad.len() as u128, plaintext.len() as u128)?;
+        self.track_nonce(nonce);
+        let mut state = self.start(nonce, aad)?;
+        let mut chacha20 = state.chacha20.clone();
+
+        let mut sealed = Vec::new();
+        sealed
+            .try_reserve(plaintext.len() + TAG_SIZE)
+            .map_err(|_| error::Error::OutOfMemory)?;
+        sealed.extend_from_slice(plaintext);
+
+        state.authenticate(plaintext);
+        sealed.extend_from_slice(&state.finalize());
+
+        chacha20.perform_in_place(&mut sealed)?;
+        Ok(sealed)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Decrypts a message produced by `mac_then_encrypt` and verifies the tag over the recovered
+    /// plaintext. See `mac_then_encrypt` for the weaker security properties of this construction.
+    pub fn decrypt_then_verify(&self, nonce: &Nonce, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        let split_at = sealed
+            .len()
+            .checked_sub(TAG_SIZE)
+            .ok_or(error::Error::Unauthenticated)?;
+
+        let mut state = self.start(nonce, aad)?;
+        let mut data = try_to_vec(sealed)?;
+        state.chacha20.clone().perform_in_place(&mut data)?;
+
+        let (plaintext, tag) = data.split_at(split_at);
+        state.authenticate(plaintext);
+
+        // Unwrap here is safe because the tag slice is exactly TAG_SIZE bytes long
+        if let Err(err) = state.verify(tag.try_into().unwrap()) {
+            data.zeroize();
+            return Err(err);
+        }
+
+        data.truncate(split_at);
         Ok(data)
     }
-}
 
-pub(crate) fn gen_poly1305_key(cipher: &mut ChaCha20) -> Poly1305Key {
-    let cnt = cipher.current_position();
-    cipher.seek_to(0);
-    let mut poly1305_key: Poly1305Key = Default::default();
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext like `encrypt` and additionally returns a Poly1305 tag over the
+    /// plaintext alone, so the integrity of restored data can be checked independently of the
+    /// AEAD with `verify_plaintext_tag`.
+    ///
+    /// The plaintext tag uses the second half of the first keystream block of the nonce as its
+    /// one-time key, which is otherwise left unused by the AEAD. With split keys, the block 0
+    /// encrypts the data, so the last keystream block of the nonce is used instead, which no
+    /// plaintext within `MAX_PLAINTEXT_LEN` reaches.
+    pub fn seal_with_plaintext_tag(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, Tag)> {
+        let plaintext_tag = self.plaintext_tag(nonce, plaintext);
+        Ok((self.encrypt(nonce, aad, plaintext)?, plaintext_tag))
+    }
 
-    // Unwrap here is safe because we only encrypt 32 bytes of data
-    // with the internal counter set to 0
-    cipher.perform_in_place(&mut poly1305_key).unwrap();
-    cipher.seek_to(cnt);
-    poly1305_key
-}
+    /// Verifies in constant time a plaintext tag returned by `seal_with_plaintext_tag`.
+    pub fn verify_plaintext_tag(&self, nonce: &Nonce, plaintext: &[u8], tag: &Tag) -> bool {
+        poly1305::verify_tag(&self.plaintext_tag(nonce, plaintext), tag)
+    }
 
-#[cfg(test)]
-mod test {
-    use super::gen_poly1305_key;
-    use crate::*;
+    fn plaintext_tag(&self, nonce: &Nonce, plaintext: &[u8]) -> Tag {
+        let mut block = [0; 64];
+        let counter = match self.mac_key {
+            Some(_) => u32::MAX,
+            None => 0,
+        };
 
-    #[test]
-    fn rfc_8439_example_vector() -> Result<()> {
-        let text = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
-        let aad = &[
-            0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
-        ];
+        // Unwrap here is safe because we only generate one block of keystream,
+        // which is always available for a single block
+        ChaCha20::new_with_cnt(&self.key, nonce, counter)
+            .fill_keystream(&mut block)
+            .unwrap();
 
-        let key = [
-            0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d,
-            0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
-            0x9c, 0x9d, 0x9e, 0x9f,
-        ];
+        // Unwrap here is safe because the second half of the block is 32 bytes long
+        let mut poly1305_key: Poly1305Key = block[32..].try_into().unwrap();
+        block.zeroize();
 
-        let nonce = [
-            0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
-        ];
+        let tag = Poly1305::compute(&poly1305_key, plaintext);
+        poly1305_key.zeroize();
+        tag
+    }
 
-        let expected_cryptogram = [
-            0xd3, 0x1a, 0x8d, 0x34, 0x64, 0x8e, 0x60, 0xdb, 0x7b, 0x86, 0xaf, 0xbc, 0x53, 0xef,
-            0x7e, 0xc2, 0xa4, 0xad, 0xed, 0x51, 0x29, 0x6e, 0x08, 0xfe, 0xa9, 0xe2, 0xb5, 0xa7,
-            0x36, 0xee, 0x62, 0xd6, 0x3d, 0xbe, 0xa4, 0x5e, 0x8c, 0xa9, 0x67, 0x12, 0x82, 0xfa,
-            0xfb, 0x69, 0xda, 0x92, 0x72, 0x8b, 0x1a, 0x71, 0xde, 0x0a, 0x9e, 0x06, 0x0b, 0x29,
-            0x05, 0xd6, 0xa5, 0xb6, 0x7e, 0xcd, 0x3b, 0x36, 0x92, 0xdd, 0xbd, 0x7f, 0x2d, 0x77,
-            0x8b, 0x8c, 0x98, 0x03, 0xae, 0xe3, 0x28, 0x09, 0x1b, 0x58, 0xfa, 0xb3, 0x24, 0xe4,
-            0xfa, 0xd6, 0x75, 0x94, 0x55, 0x85, 0x80, 0x8b, 0x48, 0x31, 0xd7, 0xbc, 0x3f, 0xf4,
-            0xde, 0xf0, 0x8e, 0x4b, 0x7a, 0x9d, 0xe5, 0x76, 0xd2, 0x65, 0x86, 0xce, 0xc6, 0x4b,
-            0x61, 0x16,
-        ];
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext like `encrypt` and also returns a 16 bytes content ID of the sealed
+    /// message, for deduplication in content-addressable stores.
+    ///
+    /// The content ID is the Poly1305 tag of the returned ciphertext and tag under a fixed public
+    /// key, so anyone can recompute it from the sealed message. It is a fingerprint and not an
+    /// authenticator: it is not a secret, and it says nothing about who sealed the message. Sealing
+    /// the same plaintext and AAD with the same key and nonce yields the same content ID.
+    pub fn seal_with_content_id(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, [u8; 16])> {
+        let sealed = self.encrypt(nonce, aad, plaintext)?;
+        let content_id = Poly1305::compute(&CONTENT_ID_KEY, &sealed);
+        Ok((sealed, content_id))
+    }
 
-        let expected_tag = [
-            0x1a, 0xe1, 0x0b, 0x59, 0x4f, 0x09, 0xe2, 0x6a, 0x7e, 0x90, 0x2e, 0xcb, 0xd0, 0x60,
-            0x06, 0x91,
-        ];
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext and authenticates a `trailer` transmitted in the clear after it,
+    /// such as the checksum of a packet.
+    ///
+    /// The returned message is `ciphertext || trailer || tag`, where the tag is computed like the
+    /// AEAD tag with `ciphertext || trailer` in place of the ciphertext, so `aad`, the ciphertext
+    /// and the trailer are all authenticated.
+    pub fn encrypt_with_trailer(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        plaintext: &[u8],
+        trailer: &[u8],
+    ) -> Result<Vec<u8>> {
+        check_lengths(aad.len() as u128, plaintext.len() as u128)?;
 
-        let cipher = ChaCha20Poly1305::new(&key, &nonce, aad)?;
-        let mut res = text.clone();
+        let sealed_len = plaintext
+            .len()
+            .checked_add(trailer.len())
+            .and_then(|len| len.checked_add(TAG_SIZE))
+            .ok_or(error::Error::DataTooLong)?;
 
-        let tag = cipher.encrypt_oneshot_in_place(&mut res)?;
+        self.track_nonce(nonce);
+        let mut state = self.start(nonce, aad)?;
 
-        assert_eq!(&res, &expected_cryptogram);
-        assert_eq!(&tag, &expected_tag);
+        let mut sealed = Vec::new();
+        sealed
+            .try_reserve(sealed_len)
+            .map_err(|_| error::Error::OutOfMemory)?;
+        sealed.extend_from_slice(plaintext);
 
-        // Decipher side
-        let cipher = ChaCha20Poly1305::new(&key, &nonce, aad)?;
-        cipher.decrypt_oneshot_in_place(&mut res, &tag)?;
+        state.encrypt_in_place(&mut sealed)?;
+        state.authenticate(trailer);
+        sealed.extend_from_slice(trailer);
+        sealed.extend_from_slice(&state.finalize());
 
-        assert_eq!(&res, text);
+        Ok(sealed)
+    }
 
-        Ok(())
+    #[cfg(feature = "alloc")]
+    /// Verifies a message produced by `encrypt_with_trailer` with a trailer of `trailer_len` bytes
+    /// and returns the decrypted plaintext. The trailer itself is read in the clear from `sealed`.
+    pub fn decrypt_with_trailer(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        sealed: &[u8],
+        trailer_len: usize,
+    ) -> Result<Vec<u8>> {
+        let ct_len = TAG_SIZE
+            .checked_add(trailer_len)
+            .and_then(|len| sealed.len().checked_sub(len))
+            .ok_or(error::Error::Unauthenticated)?;
+
+        let (authenticated, tag) = sealed.split_at(ct_len + trailer_len);
+        let mut state = self.start(nonce, aad)?;
+        let mut chacha20 = state.chacha20.clone();
+
+        state.authenticate(authenticated);
+        // Unwrap here is safe because the tag slice is exactly TAG_SIZE bytes long
+        state.verify(tag.try_into().unwrap())?;
+
+        let mut plaintext = try_to_vec(&authenticated[..ct_len])?;
+        chacha20.perform_in_place(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext like `encrypt`, binding a `context` label into the authentication.
+    ///
+    /// The AAD actually authenticated is the length of `context` as a 64-bit little-endian
+    /// integer, followed by `context` and `aad`, so that messages of different types sealed under
+    /// the same key cannot be opened as each other.
+    pub fn encrypt_with_context(
+        &self,
+        nonce: &Nonce,
+        context: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.encrypt(nonce, &context_aad(context, aad)?, plaintext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Decrypts a message produced by `encrypt_with_context` under the same `context`.
+    pub fn decrypt_with_context(
+        &self,
+        nonce: &Nonce,
+        context: &[u8],
+        aad: &[u8],
+        ct_with_tag: &[u8],
+    ) -> Result<Vec<u8>> {
+        Ok(self.decrypt(nonce, &context_aad(context, aad)?, ct_with_tag)?)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext like `encrypt`, binding its length into the AAD as an 8 bytes
+    /// little-endian prefix, so the length is committed to on top of the tag.
+    ///
+    /// The sealed message has the same layout as with `encrypt`, it must be opened with
+    /// `decrypt_length_committed`.
+    pub fn encrypt_length_committed(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.encrypt(nonce, &length_aad(plaintext.len(), aad)?, plaintext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Decrypts a message sealed with `encrypt_length_committed`, recomputing the committed length
+    /// from the length of the received ciphertext.
+    pub fn decrypt_length_committed(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        ct_with_tag: &[u8],
+    ) -> Result<Vec<u8>> {
+        let len = ct_with_tag
+            .len()
+            .checked_sub(TAG_SIZE)
+            .ok_or(error::Error::Unauthenticated)?;
+
+        Ok(self.decrypt(nonce, &length_aad(len, aad)?, ct_with_tag)?)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext like `encrypt`, but keeps only the first `tag_len` bytes of the tag,
+    /// for constrained protocols mandating truncated tags.
+    ///
+    /// The tag length is bound into the AAD as a single leading byte, so a message sealed with one
+    /// tag length never verifies with another, and a tag cannot be truncated further to downgrade
+    /// it. Returns `Error::InvalidLength` unless `tag_len` is between 1 and 16.
+    ///
+    /// # Security
+    ///
+    /// A truncated tag can be forged with a probability of about `2^-(8 * tag_len)` per attempt,
+    /// see `Poly1305::verify_truncated`.
+    pub fn encrypt_with_tag_len(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        plaintext: &[u8],
+        tag_len: usize,
+    ) -> Result<Vec<u8>> {
+        let aad = tag_len_aad(tag_len, aad)?;
+        let (mut ciphertext, tag) = self.encrypt_detached(nonce, &aad, plaintext)?;
+
+        ciphertext
+            .try_reserve(tag_len)
+            .map_err(|_| error::Error::OutOfMemory)?;
+        ciphertext.extend_from_slice(&tag[..tag_len]);
+
+        Ok(ciphertext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Decrypts a message sealed with `encrypt_with_tag_len` with the same `tag_len`, verifying the
+    /// truncated tag in constant time before allocating the plaintext.
+    pub fn decrypt_with_tag_len(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        ct_with_tag: &[u8],
+        tag_len: usize,
+    ) -> Result<Vec<u8>> {
+        let aad = tag_len_aad(tag_len, aad)?;
+        let split_at = ct_with_tag
+            .len()
+            .checked_sub(tag_len)
+            .ok_or(error::Error::Unauthenticated)?;
+        let (ciphertext, tag) = ct_with_tag.split_at(split_at);
+
+        let mut state = self.start(nonce, &aad)?;
+        let mut chacha20 = state.chacha20.clone();
+        state.authenticate(ciphertext);
+        state.verify_truncated(tag)?;
+
+        let mut plaintext = try_to_vec(ciphertext)?;
+        chacha20.perform_in_place(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Encrypts the plaintext like `encrypt`, authenticating structured metadata given as
+    /// key-value pairs instead of a raw AAD, independently of the order of the pairs.
+    ///
+    /// The pairs are sorted by key, then by value, and each of them is encoded as the key length
+    /// as a 64-bit little-endian integer, the key, the value length encoded the same way and the
+    /// value. The concatenation of the encoded pairs is the AAD. Duplicate keys are kept.
+    pub fn encrypt_with_kv_aad(
+        &self,
+        nonce: &Nonce,
+        kv: &[(&[u8], &[u8])],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.encrypt(nonce, &kv_aad(kv)?, plaintext)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Decrypts a message produced by `encrypt_with_kv_aad` with the same key-value pairs, in any
+    /// order.
+    pub fn decrypt_with_kv_aad(
+        &self,
+        nonce: &Nonce,
+        kv: &[(&[u8], &[u8])],
+        ct_with_tag: &[u8],
+    ) -> Result<Vec<u8>> {
+        Ok(self.decrypt(nonce, &kv_aad(kv)?, ct_with_tag)?)
+    }
+
+    #[cfg(all(feature = "alloc", feature = "subtle"))]
+    /// Decrypts `ct_with_tag` (ciphertext followed by the 16 bytes tag) without branching on the
+    /// verification result.
+    ///
+    /// Returns a `Choice` of 1 together with the plaintext when the tag is valid. Otherwise the
+    /// `Choice` is 0 and the returned plaintext is zeroized.
+    pub fn open_ct(&self, nonce: &Nonce, aad: &[u8], ct_with_tag: &[u8]) -> (Choice, Vec<u8>) {
+        let Some(split_at) = ct_with_tag.len().checked_sub(TAG_SIZE) else {
+            return (Choice::from(0), Vec::new());
+        };
+
+        let (ciphertext, tag) = ct_with_tag.split_at(split_at);
+
+        let Ok(mut state) = self.start(nonce, aad) else {
+            return (Choice::from(0), Vec::new());
+        };
+
+        let Ok(mut plaintext) = try_to_vec(ciphertext) else {
+            return (Choice::from(0), Vec::new());
+        };
+
+        if state.decrypt_in_place(&mut plaintext).is_err() {
+            plaintext.zeroize();
+            return (Choice::from(0), plaintext);
+        }
+
+        let valid = state.finalize().ct_eq(tag);
+
+        plaintext
+            .iter_mut()
+            .for_each(|v| *v = u8::conditional_select(&0, v, valid));
+
+        (valid, plaintext)
+    }
+}
+
+/// The one-time authenticator of the AEAD construction.
+///
+/// Only `Poly1305` is used outside of the tests, the trait exists so the tests can substitute an
+/// instrumented authenticator and observe the exact byte sequence being authenticated.
+pub(crate) trait Mac: Sized {
+    /// Creates the authenticator from the one-time key derived from the block 0.
+    fn new(key: &Poly1305Key) -> Self;
+
+    /// Authenticates the next part of the data.
+    fn update(&mut self, data: &[u8]);
+
+    /// Pads the data authenticated so far with zeros up to a multiple of 16 bytes.
+    fn update_leftover_pad16(&mut self);
+
+    /// Returns the tag of the authenticated data.
+    fn finalize(self) -> Tag;
+
+    /// Compares the tag of the authenticated data with `tag` in constant time.
+    fn verify(self, tag: &Tag) -> bool {
+        poly1305::verify_tag(&self.finalize(), tag)
+    }
+}
+
+impl Mac for Poly1305 {
+    #[inline(always)]
+    fn new(key: &Poly1305Key) -> Self {
+        Poly1305::new(key)
+    }
+
+    #[inline(always)]
+    fn update(&mut self, data: &[u8]) {
+        Poly1305::update(self, data)
+    }
+
+    #[inline(always)]
+    fn update_leftover_pad16(&mut self) {
+        Poly1305::update_leftover_pad16(self)
+    }
+
+    #[inline(always)]
+    fn finalize(self) -> Tag {
+        Poly1305::finalize(self)
+    }
+
+    #[inline(always)]
+    fn verify(self, tag: &Tag) -> bool {
+        Poly1305::verify(self, tag)
+    }
+}
+
+/// Represents the state of a single message processed by the ChaCha20-Poly1305 AEAD cipher.
+pub struct AeadState<M = Poly1305> {
+    pub(crate) chacha20: ChaCha20,
+    pub(crate) mac: M,
+    data_len: u64,
+    aad_len: u64,
+}
+
+impl AeadState {
+    /// Creates a new AeadState instance with the provided key, nonce, and additional authenticated data (AAD).
+    pub fn new(key: &Key, nonce: &Nonce, aad: &[u8]) -> Result<Self> {
+        Self::new_with_mac(key, nonce, aad)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Verifies a truncated tag like `verify`, see `Poly1305::verify_truncated`
+    fn verify_truncated(mut self, tag: &[u8]) -> Result<()> {
+        self.mac.update_leftover_pad16();
+        self.auth_len();
+
+        if !self.mac.verify_truncated(tag) {
+            Err(error::Error::Unauthenticated)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// `Mac` is private, so outside of the crate only `AeadState<Poly1305>` can be used
+#[allow(private_bounds)]
+impl<M: Mac> AeadState<M> {
+    /// Creates the state of a message authenticated by `M`, like `new`.
+    fn new_with_mac(key: &Key, nonce: &Nonce, aad: &[u8]) -> Result<Self> {
+        let mut chacha20 = ChaCha20::new_with_cnt(key, nonce, 1);
+        let mut poly1305_key = gen_poly1305_key(&mut chacha20);
+        let res = Self::with_mac_key(chacha20, &poly1305_key, aad);
+
+        poly1305_key.zeroize();
+        res
+    }
+
+    /// Creates the state from a ChaCha20 instance positioned at the block 1 and the one-time
+    /// key already derived from the block 0.
+    fn with_mac_key(chacha20: ChaCha20, mac_key: &Poly1305Key, aad: &[u8]) -> Result<Self> {
+        let mut mac = M::new(mac_key);
+
+        mac.update(aad);
+        mac.update_leftover_pad16();
+
+        Ok(Self {
+            chacha20,
+            mac,
+            data_len: 0,
+            aad_len: u64::try_from(aad.len()).map_err(|_| error::Error::AadTooLong)?,
+        })
+    }
+
+    #[inline]
+    /// Encrypts the provided data in-place.
+    pub fn encrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        self.chacha20.perform_in_place(data)?;
+        self.mac.update(data);
+        self.data_len += u64::try_from(data.len()).unwrap();
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[inline(always)]
+    /// Encrypts the provided data and returns the result as a new vector.
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut data = try_to_vec(data)?;
+        self.encrypt_in_place(&mut data)?;
+        Ok(data)
+    }
+
+    #[inline]
+    /// Decrypts the provided data in-place.
+    pub fn decrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        self.mac.update(data);
+        self.chacha20.perform_in_place(data)?;
+        self.data_len += u64::try_from(data.len()).unwrap();
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[inline(always)]
+    /// Decrypts the provided data and returns the result as a new vector.
+    pub fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut data = try_to_vec(data)?;
+        self.decrypt_in_place(&mut data)?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "alloc")]
+    #[inline]
+    fn authenticate(&mut self, data: &[u8]) {
+        self.mac.update(data);
+        self.data_len += u64::try_from(data.len()).unwrap();
+    }
+
+    #[inline]
+    fn auth_len(&mut self) {
+        self.mac.update(&self.aad_len.to_le_bytes());
+        self.mac.update(&self.data_len.to_le_bytes());
+    }
+
+    #[inline]
+    /// Get the `Tag` of the processed data
+    pub fn finalize(mut self) -> Tag {
+        self.mac.update_leftover_pad16();
+        self.auth_len();
+        self.mac.finalize()
+    }
+
+    #[inline]
+    /// Verify the `Tag` with the processed
+    pub fn verify(mut self, tag: &Tag) -> Result<()> {
+        self.mac.update_leftover_pad16();
+        self.auth_len();
+
+        if !self.mac.verify(tag) {
+            Err(error::Error::Unauthenticated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Encrypts the provided data in-place in a one-shot operation and returns the authentication tag.
+    pub fn encrypt_oneshot_in_place(mut self, data: &mut [u8]) -> Result<Tag> {
+        for chunk in data.chunks_mut(DATA_CHUNK_SIZE) {
+            self.chacha20.perform_in_place(chunk)?;
+            self.mac.update(chunk);
+        }
+
+        self.data_len = u64::try_from(data.len()).unwrap();
+
+        Ok(self.finalize())
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Encrypts the provided data in a one-shot operation and returns the result as a new vector along with the authentication tag.
+    pub fn encrypt_oneshot(self, data: &[u8]) -> Result<(Vec<u8>, Tag)> {
+        let mut data = try_to_vec(data)?;
+        let tag = self.encrypt_oneshot_in_place(&mut data)?;
+        Ok((data, tag))
+    }
+
+    /// Decrypts the provided data in-place in a one-shot operation and verifies the authentication tag.
+    /// The tag is verified before anything is decrypted, so `data` is left untouched on failure.
+    pub fn decrypt_oneshot_in_place(mut self, data: &mut [u8], tag: &Tag) -> Result<()> {
+        self.mac.update(data);
+        self.data_len = u64::try_from(data.len()).unwrap();
+        self.mac.update_leftover_pad16();
+        self.auth_len();
+
+        if !self.mac.verify(tag) {
+            return Err(error::Error::Unauthenticated);
+        }
+
+        self.chacha20.perform_in_place(data)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Decrypts the provided data in a one-shot operation, verifying the authentication tag, and returns the result as a new vector.
+    /// The plaintext is only allocated once the tag is verified, nothing is decrypted on failure.
+    pub fn decrypt_oneshot(mut self, data: &[u8], tag: &Tag) -> Result<Vec<u8>> {
+        let mut chacha20 = self.chacha20.clone();
+
+        self.authenticate(data);
+        self.verify(tag)?;
+
+        let mut data = try_to_vec(data)?;
+        chacha20.perform_in_place(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Encrypts a single message incrementally, for data that is not available at once.
+/// Created by `ChaCha20Poly1305::encryptor`.
+pub struct StreamEncryptor {
+    state: AeadState,
+    aad_finished: bool,
+}
+
+impl StreamEncryptor {
+    /// Appends `aad` to the additional authenticated data of the message.
+    /// Returns `Error::AadAfterData` once `update` has been called.
+    pub fn update_aad(&mut self, aad: &[u8]) -> Result<()> {
+        if self.aad_finished {
+            return Err(error::Error::AadAfterData);
+        }
+
+        let len = u64::try_from(aad.len()).map_err(|_| error::Error::AadTooLong)?;
+        self.state.aad_len = self
+            .state
+            .aad_len
+            .checked_add(len)
+            .ok_or(error::Error::AadTooLong)?;
+        self.state.mac.update(aad);
+        Ok(())
+    }
+
+    /// Encrypts the next part of the plaintext in-place, ending the AAD.
+    pub fn update(&mut self, data: &mut [u8]) -> Result<()> {
+        if !self.aad_finished {
+            self.state.mac.update_leftover_pad16();
+            self.aad_finished = true;
+        }
+
+        self.state.encrypt_in_place(data)
+    }
+
+    #[inline]
+    /// Finishes the message and returns its authentication tag.
+    pub fn finalize(self) -> Tag {
+        // The padding of the AAD is still pending when no data has been encrypted,
+        // it is applied by the padding of the data
+        self.state.finalize()
+    }
+}
+
+/// Encrypts a single message pushed piece by piece, for a writer that has to flush the ciphertext
+/// downstream before accepting more plaintext.
+///
+/// The ciphertext is produced a whole keystream block at a time from an internal buffer, so at most
+/// 63 bytes of plaintext stay buffered between pushes. They are zeroized when the sealer is dropped.
+#[cfg(feature = "alloc")]
+pub struct StreamingSealer {
+    state: AeadState,
+    buffer: zeroize::Zeroizing<Vec<u8>>,
+    emitted: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl StreamingSealer {
+    /// Encrypts the next part of the plaintext and returns the ciphertext produced so far that has
+    /// not been returned yet. The returned slice is only valid until the next call.
+    pub fn push(&mut self, plaintext: &[u8]) -> Result<&[u8]> {
+        self.buffer.drain(..self.emitted);
+        self.emitted = 0;
+
+        self.buffer
+            .try_reserve(plaintext.len())
+            .map_err(|_| error::Error::OutOfMemory)?;
+        self.buffer.extend_from_slice(plaintext);
+
+        let len = self.buffer.len() - self.buffer.len() % DATA_CHUNK_SIZE;
+        self.state.encrypt_in_place(&mut self.buffer[..len])?;
+        self.emitted = len;
+
+        Ok(&self.buffer[..len])
+    }
+
+    /// Encrypts the buffered plaintext and returns it with the authentication tag of the message.
+    pub fn finish(mut self) -> Result<(Vec<u8>, Tag)> {
+        let pending = &mut self.buffer[self.emitted..];
+        self.state.encrypt_in_place(pending)?;
+
+        let tail = try_to_vec(pending)?;
+        Ok((tail, self.state.finalize()))
+    }
+}
+
+/// Decrypts a single message fed piece by piece, as `ciphertext || tag` split at arbitrary points.
+///
+/// The last 16 bytes received so far are always held back, since they may turn out to be the tag,
+/// and the bytes before them are decrypted as soon as they arrive.
+///
+/// # Security
+///
+/// The plaintext handed out by `update` is **not authenticated** until `finalize` succeeds. It must
+/// not be acted upon, or must be discarded along with everything derived from it, if `finalize`
+/// fails.
+#[cfg(feature = "alloc")]
+pub struct StreamingOpener {
+    state: AeadState,
+    buffer: zeroize::Zeroizing<Vec<u8>>,
+    tail: Tag,
+    tail_len: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl StreamingOpener {
+    /// Feeds the next part of the sealed message and returns the plaintext it released, which is
+    /// only valid until the next call.
+    pub fn update(&mut self, data: &[u8]) -> Result<&[u8]> {
+        self.buffer.zeroize();
+
+        let total = self.tail_len + data.len();
+        let release = total.saturating_sub(TAG_SIZE);
+        self.buffer
+            .try_reserve(release)
+            .map_err(|_| error::Error::OutOfMemory)?;
+
+        // The held back bytes followed by the new ones, the first `release` bytes are ciphertext
+        // for sure and the last 16 at most are held back as the new tail
+        let mut tail = [0; TAG_SIZE];
+        let mut tail_len = 0;
+        for (i, &byte) in self.tail[..self.tail_len].iter().chain(data).enumerate() {
+            if i < release {
+                self.buffer.push(byte);
+            } else {
+                tail[tail_len] = byte;
+                tail_len += 1;
+            }
+        }
+
+        self.tail = tail;
+        self.tail_len = tail_len;
+
+        self.state.decrypt_in_place(&mut self.buffer)?;
+        Ok(&self.buffer)
+    }
+
+    /// Verifies the held back tag. Returns `Error::Unauthenticated` if the message is shorter than
+    /// a tag or the tag is invalid, in which case all the released plaintext must be discarded.
+    pub fn finalize(self) -> Result<()> {
+        if self.tail_len != TAG_SIZE {
+            return Err(error::Error::Unauthenticated);
+        }
+
+        self.state.verify(&self.tail)
+    }
+}
+
+/// Derives the nonce of a record by XORing its index into the last 8 bytes of the base nonce
+fn record_nonce(base_nonce: &Nonce, record_index: u64) -> Nonce {
+    let mut nonce = *base_nonce;
+
+    nonce[4..]
+        .iter_mut()
+        .zip(record_index.to_be_bytes())
+        .for_each(|(v, i)| *v ^= i);
+
+    nonce
+}
+
+/// Ring buffer of the last nonces sealed by a `ChaCha20Poly1305` instance.
+#[cfg(feature = "debug-nonce-checks")]
+struct NonceLog {
+    nonces: [Nonce; NONCE_LOG_SIZE],
+    len: usize,
+    next: usize,
+}
+
+#[cfg(feature = "debug-nonce-checks")]
+impl NonceLog {
+    const fn new() -> Self {
+        Self {
+            nonces: [[0; 12]; NONCE_LOG_SIZE],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Records the nonce, evicting the oldest one when full. Returns false if it is already logged.
+    fn insert(&mut self, nonce: &Nonce) -> bool {
+        if self.nonces[..self.len].contains(nonce) {
+            return false;
+        }
+
+        self.nonces[self.next] = *nonce;
+        self.next = (self.next + 1) % NONCE_LOG_SIZE;
+        self.len = (self.len + 1).min(NONCE_LOG_SIZE);
+        true
+    }
+}
+
+/// Checks that a batch of nonces contains no duplicate before sealing messages with them.
+///
+/// Returns `Err(index)` with the index of the first nonce that repeats an earlier one. With the
+/// `alloc` feature the check sorts a copy of the nonces, otherwise it compares every pair.
+pub fn check_nonces_unique(nonces: &[Nonce]) -> core::result::Result<(), usize> {
+    #[cfg(feature = "alloc")]
+    {
+        let mut sorted: Vec<(&Nonce, usize)> = nonces.iter().zip(0..).collect();
+        sorted.sort_unstable();
+
+        match sorted
+            .windows(2)
+            .filter(|pair| pair[0].0 == pair[1].0)
+            .map(|pair| pair[1].1)
+            .min()
+        {
+            Some(index) => Err(index),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    {
+        match (0..nonces.len()).find(|&i| nonces[..i].contains(&nonces[i])) {
+            Some(index) => Err(index),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Builds the AAD binding the length of a truncated tag in front of the user AAD
+fn tag_len_aad(tag_len: usize, aad: &[u8]) -> Result<Vec<u8>> {
+    if !(1..=TAG_SIZE).contains(&tag_len) {
+        return Err(error::Error::InvalidLength);
+    }
+
+    let mut res = Vec::new();
+    res.try_reserve(1 + aad.len())
+        .map_err(|_| error::Error::OutOfMemory)?;
+    res.push(tag_len as u8);
+    res.extend_from_slice(aad);
+
+    Ok(res)
+}
+
+#[cfg(feature = "alloc")]
+/// Builds the AAD committing to the plaintext length in front of the user AAD
+fn length_aad(plaintext_len: usize, aad: &[u8]) -> Result<Vec<u8>> {
+    let len = (plaintext_len as u64).to_le_bytes();
+
+    let mut res = Vec::new();
+    res.try_reserve(len.len() + aad.len())
+        .map_err(|_| error::Error::OutOfMemory)?;
+    res.extend_from_slice(&len);
+    res.extend_from_slice(aad);
+
+    Ok(res)
+}
+
+#[cfg(feature = "alloc")]
+/// Builds the AAD binding a length-prefixed context label in front of the user AAD
+fn context_aad(context: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let len = (context.len() as u64).to_le_bytes();
+
+    let mut res = Vec::new();
+    res.try_reserve(len.len() + context.len() + aad.len())
+        .map_err(|_| error::Error::OutOfMemory)?;
+    res.extend_from_slice(&len);
+    res.extend_from_slice(context);
+    res.extend_from_slice(aad);
+
+    Ok(res)
+}
+
+#[cfg(feature = "alloc")]
+/// Builds the canonical AAD of key-value pairs, see `ChaCha20Poly1305::encrypt_with_kv_aad`
+fn kv_aad(kv: &[(&[u8], &[u8])]) -> Result<Vec<u8>> {
+    let mut pairs = Vec::new();
+    pairs
+        .try_reserve(kv.len())
+        .map_err(|_| error::Error::OutOfMemory)?;
+    pairs.extend_from_slice(kv);
+    pairs.sort_unstable();
+
+    let len = pairs.iter().map(|(k, v)| 16 + k.len() + v.len()).sum();
+    let mut res = Vec::new();
+    res.try_reserve(len)
+        .map_err(|_| error::Error::OutOfMemory)?;
+
+    for (key, value) in pairs {
+        res.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        res.extend_from_slice(key);
+        res.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        res.extend_from_slice(value);
+    }
+
+    Ok(res)
+}
+
+#[cfg(feature = "testing")]
+/// Encrypts the plaintext and returns a record of the inputs and outputs for diffing against the
+/// vectors of another implementation, one `name: hex` line per field:
+///
+/// ```text
+/// key: ...
+/// nonce: ...
+/// aad: ...
+/// plaintext: ...
+/// ciphertext: ...
+/// tag: ...
+/// ```
+pub fn dump_vector(
+    key: &Key,
+    nonce: &Nonce,
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<alloc::string::String> {
+    use core::fmt::Write as _;
+
+    let (ciphertext, tag) = ChaCha20Poly1305::new(key).encrypt_detached(nonce, aad, plaintext)?;
+    let mut dump = alloc::string::String::new();
+
+    for (name, value) in [
+        ("key", &key[..]),
+        ("nonce", &nonce[..]),
+        ("aad", aad),
+        ("plaintext", plaintext),
+        ("ciphertext", &ciphertext),
+        ("tag", &tag),
+    ] {
+        dump.push_str(name);
+        dump.push_str(": ");
+        // Unwrap here is safe because writing to a String never fails
+        value.iter().for_each(|b| write!(dump, "{b:02x}").unwrap());
+        dump.push('\n');
+    }
+
+    Ok(dump)
+}
+
+/// Checks the lengths of a message against `MAX_AAD_LEN` and `MAX_PLAINTEXT_LEN`, they are
+/// widened to `u128` so the check holds on every pointer width.
+#[inline]
+fn check_lengths(aad_len: u128, plaintext_len: u128) -> Result<()> {
+    if aad_len > u128::from(MAX_AAD_LEN) {
+        return Err(error::Error::AadTooLong);
+    }
+
+    if plaintext_len > u128::from(MAX_PLAINTEXT_LEN) {
+        return Err(error::Error::DataTooLong);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn gen_poly1305_key(cipher: &mut ChaCha20) -> Poly1305Key {
+    let cnt = cipher.current_position();
+    cipher.seek_to(0);
+    let mut poly1305_key: Poly1305Key = Default::default();
+
+    // Unwrap here is safe because we only encrypt 32 bytes of data
+    // with the internal counter set to 0
+    cipher.perform_in_place(&mut poly1305_key).unwrap();
+    cipher.seek_to(cnt);
+    poly1305_key
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_lengths, check_nonces_unique, gen_poly1305_key, AeadState};
+    use crate::*;
+
+    #[test]
+    fn rfc_8439_example_vector() -> Result<()> {
+        let text = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let aad = &[
+            0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+        ];
+
+        let key = [
+            0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d,
+            0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
+            0x9c, 0x9d, 0x9e, 0x9f,
+        ];
+
+        let nonce = [
+            0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+        ];
+
+        let expected_cryptogram = [
+            0xd3, 0x1a, 0x8d, 0x34, 0x64, 0x8e, 0x60, 0xdb, 0x7b, 0x86, 0xaf, 0xbc, 0x53, 0xef,
+            0x7e, 0xc2, 0xa4, 0xad, 0xed, 0x51, 0x29, 0x6e, 0x08, 0xfe, 0xa9, 0xe2, 0xb5, 0xa7,
+            0x36, 0xee, 0x62, 0xd6, 0x3d, 0xbe, 0xa4, 0x5e, 0x8c, 0xa9, 0x67, 0x12, 0x82, 0xfa,
+            0xfb, 0x69, 0xda, 0x92, 0x72, 0x8b, 0x1a, 0x71, 0xde, 0x0a, 0x9e, 0x06, 0x0b, 0x29,
+            0x05, 0xd6, 0xa5, 0xb6, 0x7e, 0xcd, 0x3b, 0x36, 0x92, 0xdd, 0xbd, 0x7f, 0x2d, 0x77,
+            0x8b, 0x8c, 0x98, 0x03, 0xae, 0xe3, 0x28, 0x09, 0x1b, 0x58, 0xfa, 0xb3, 0x24, 0xe4,
+            0xfa, 0xd6, 0x75, 0x94, 0x55, 0x85, 0x80, 0x8b, 0x48, 0x31, 0xd7, 0xbc, 0x3f, 0xf4,
+            0xde, 0xf0, 0x8e, 0x4b, 0x7a, 0x9d, 0xe5, 0x76, 0xd2, 0x65, 0x86, 0xce, 0xc6, 0x4b,
+            0x61, 0x16,
+        ];
+
+        let expected_tag = [
+            0x1a, 0xe1, 0x0b, 0x59, 0x4f, 0x09, 0xe2, 0x6a, 0x7e, 0x90, 0x2e, 0xcb, 0xd0, 0x60,
+            0x06, 0x91,
+        ];
+
+        let cipher = AeadState::new(&key, &nonce, aad)?;
+        let mut res = *text;
+
+        let tag = cipher.encrypt_oneshot_in_place(&mut res)?;
+
+        assert_eq!(&res, &expected_cryptogram);
+        assert_eq!(&tag, &expected_tag);
+
+        // Decipher side
+        let cipher = AeadState::new(&key, &nonce, aad)?;
+        cipher.decrypt_oneshot_in_place(&mut res, &tag)?;
+
+        assert_eq!(&res, text);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn seal_framed() -> Result<()> {
+        let key: Key = [0x5f; 32];
+        let cipher = ChaCha20Poly1305::new(&key);
+        let nonce: Nonce = [0xf5; 12];
+
+        let frame = cipher.seal_framed(&nonce, b"aad", b"framed packet")?;
+        assert_eq!(frame.len(), 12 + 4 + 13 + 16);
+        assert_eq!(frame[..12], nonce);
+        assert_eq!(frame[12..16], 13u32.to_le_bytes());
+        assert_eq!(
+            frame[16..],
+            ChaCha20Poly1305::new(&key).encrypt(&nonce, b"aad", b"framed packet")?
+        );
+        assert_eq!(cipher.open_framed(b"aad", &frame)?, b"framed packet");
+
+        let empty = cipher.seal_framed(&[0xf6; 12], b"", b"")?;
+        assert_eq!(cipher.open_framed(b"", &empty)?, b"");
+
+        // Truncated frames
+        for len in [0, 10, 16, 31, frame.len() - 1] {
+            assert_eq!(
+                cipher.open_framed(b"aad", &frame[..len]).unwrap_err(),
+                error::Error::MalformedFrame
+            );
+        }
+
+        // Overlong frame
+        let mut overlong = frame.clone();
+        overlong.push(0);
+        assert_eq!(
+            cipher.open_framed(b"aad", &overlong).unwrap_err(),
+            error::Error::MalformedFrame
+        );
+
+        // Forged header and wrong AAD
+        let mut forged = frame.clone();
+        forged[0] ^= 0x01;
+        assert_eq!(
+            cipher.open_framed(b"aad", &forged).unwrap_err(),
+            error::Error::Unauthenticated
+        );
+        assert_eq!(
+            cipher.open_framed(b"bad", &frame).unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_with_kv_aad() -> Result<()> {
+        let key: Key = [0x4b; 32];
+        let cipher = ChaCha20Poly1305::new(&key);
+        let nonce: Nonce = [0xb4; 12];
+
+        let kv: [(&[u8], &[u8]); 3] = [
+            (b"content-type", b"text/plain"),
+            (b"author", b"alice"),
+            (b"version", b"2"),
+        ];
+        let reordered = [kv[2], kv[0], kv[1]];
+
+        let sealed = cipher.encrypt_with_kv_aad(&nonce, &kv, b"metadata")?;
+        assert_eq!(
+            sealed,
+            ChaCha20Poly1305::new(&key).encrypt_with_kv_aad(&nonce, &reordered, b"metadata")?
+        );
+        assert_eq!(
+            cipher.decrypt_with_kv_aad(&nonce, &reordered, &sealed)?,
+            b"metadata"
+        );
+
+        let changed: [(&[u8], &[u8]); 3] = [kv[0], kv[1], (b"version", b"3")];
+        let other =
+            ChaCha20Poly1305::new(&key).encrypt_with_kv_aad(&nonce, &changed, b"metadata")?;
+        assert_ne!(sealed[8..], other[8..]);
+        assert_eq!(
+            cipher
+                .decrypt_with_kv_aad(&nonce, &changed, &sealed)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        // Moving bytes between a key and its value changes the AAD
+        let shifted: [(&[u8], &[u8]); 1] = [(b"ab", b"c")];
+        let unshifted: [(&[u8], &[u8]); 1] = [(b"a", b"bc")];
+        assert_ne!(
+            ChaCha20Poly1305::new(&key).encrypt_with_kv_aad(&nonce, &shifted, b"")?,
+            ChaCha20Poly1305::new(&key).encrypt_with_kv_aad(&nonce, &unshifted, b"")?
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn open_any() -> Result<()> {
+        let current: Key = [0x01; 32];
+        let previous: [Key; 2] = [[0x02; 32], [0x03; 32]];
+        let nonce: Nonce = [0x0a; 12];
+        let cipher = ChaCha20Poly1305::new(&current);
+
+        let sealed = ChaCha20Poly1305::new(&previous[1]).encrypt(&nonce, b"aad", b"rotated")?;
+        assert_eq!(
+            cipher.open_any(&previous, &nonce, b"aad", &sealed)?,
+            b"rotated"
+        );
+        assert_eq!(
+            cipher
+                .open_any(&previous[..1], &nonce, b"aad", &sealed)
+                .unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+
+        let sealed = cipher.encrypt(&nonce, b"aad", b"current")?;
+        assert_eq!(cipher.open_any(&[], &nonce, b"aad", &sealed)?, b"current");
+        assert_eq!(
+            cipher
+                .open_any(&previous, &nonce, b"other", &sealed)
+                .unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+        assert_eq!(
+            cipher
+                .open_any(&previous, &nonce, b"aad", &sealed[..8])
+                .unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn streaming_sealer() -> Result<()> {
+        let key: Key = [0x6e; 32];
+        let nonce: Nonce = [0xe6; 12];
+        let text: [u8; 300] = core::array::from_fn(|i| i as u8);
+        let cipher = ChaCha20Poly1305::new(&key);
+
+        let mut sealer = cipher.sealer(&nonce, b"aad")?;
+        let mut sealed = Vec::new();
+        for part in text.chunks(7).chain([&[][..]]) {
+            let ciphertext = sealer.push(part)?;
+            assert_eq!(ciphertext.len() % 64, 0);
+            sealed.extend_from_slice(ciphertext);
+        }
+
+        let (tail, tag) = sealer.finish()?;
+        assert_eq!(tail.len(), 300 % 64);
+        sealed.extend_from_slice(&tail);
+        sealed.extend_from_slice(&tag);
+
+        assert_eq!(cipher.decrypt(&nonce, b"aad", &sealed)?, text);
+        assert_eq!(
+            sealed,
+            ChaCha20Poly1305::new(&key).encrypt(&nonce, b"aad", &text)?
+        );
+
+        let (tail, tag) = ChaCha20Poly1305::new(&key).sealer(&nonce, b"")?.finish()?;
+        assert!(tail.is_empty());
+        assert_eq!(cipher.decrypt(&nonce, b"", &tag)?, b"");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "getrandom")]
+    #[test]
+    fn seal_open() -> Result<()> {
+        let cipher = ChaCha20Poly1305::new(&[0x5e; 32]);
+
+        let sealed = cipher.seal(b"aad", b"sealed box")?;
+        assert_eq!(sealed.len(), 12 + 10 + 16);
+        assert_eq!(cipher.open(b"aad", &sealed)?, b"sealed box");
+
+        let nonce: Nonce = sealed[..12].try_into().unwrap();
+        assert_eq!(
+            cipher.decrypt(&nonce, b"aad", &sealed[12..])?,
+            b"sealed box"
+        );
+
+        // Every call draws a fresh nonce
+        assert_ne!(cipher.seal(b"aad", b"sealed box")?[..12], sealed[..12]);
+
+        assert_eq!(
+            cipher.open(b"other", &sealed).unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+        for len in [0, 11, 12, 27] {
+            assert_eq!(
+                cipher.open(b"aad", &sealed[..len]).unwrap_err(),
+                error::DecryptError::Unauthenticated
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn streaming_opener() -> Result<()> {
+        let cipher = ChaCha20Poly1305::new(&[0x0f; 32]);
+        let nonce: Nonce = [0xf0; 12];
+        let text: [u8; 100] = core::array::from_fn(|i| i as u8);
+        let sealed = cipher.encrypt(&nonce, b"aad", &text)?;
+
+        // One byte at a time, nothing is released before 17 bytes have been fed
+        let mut opener = cipher.opener(&nonce, b"aad")?;
+        let mut plaintext = Vec::new();
+        for (i, byte) in sealed.iter().enumerate() {
+            let released = opener.update(core::slice::from_ref(byte))?;
+            assert_eq!(released.len(), usize::from(i >= 16));
+            plaintext.extend_from_slice(released);
+        }
+        opener.finalize()?;
+        assert_eq!(plaintext, text);
+
+        // Chunks straddling the tag
+        let mut opener = cipher.opener(&nonce, b"aad")?;
+        let mut plaintext = Vec::new();
+        for chunk in [
+            &sealed[..10],
+            &sealed[10..90],
+            &sealed[90..110],
+            &sealed[110..],
+        ] {
+            plaintext.extend_from_slice(opener.update(chunk)?);
+        }
+        opener.finalize()?;
+        assert_eq!(plaintext, text);
+
+        let mut forged = sealed.clone();
+        *forged.last_mut().unwrap() ^= 0x01;
+        let mut opener = cipher.opener(&nonce, b"aad")?;
+        opener.update(&forged)?;
+        assert_eq!(
+            opener.finalize().unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        let mut opener = cipher.opener(&nonce, b"aad")?;
+        opener.update(&sealed[..15])?;
+        assert_eq!(
+            opener.finalize().unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        let empty = cipher.encrypt(&[0x01; 12], b"", b"")?;
+        let mut opener = cipher.opener(&[0x01; 12], b"")?;
+        assert!(opener.update(&empty)?.is_empty());
+        opener.finalize()
+    }
+
+    #[cfg(feature = "debug-nonce-checks")]
+    #[test]
+    #[should_panic(expected = "nonce reused")]
+    fn debug_nonce_checks_panics_on_reuse() {
+        let cipher = ChaCha20Poly1305::new(&[0x33; 32]);
+        let nonce: Nonce = [0x99; 12];
+
+        let mut buffer = [0; 8];
+        let tag = cipher
+            .encrypt_in_place_detached(&nonce, b"", &mut buffer)
+            .unwrap();
+        cipher
+            .decrypt_in_place_detached(&nonce, b"", &mut buffer, &tag)
+            .unwrap();
+        let _ = cipher.encrypt_in_place_detached(&nonce, b"", &mut buffer);
+    }
+
+    #[cfg(feature = "debug-nonce-checks")]
+    #[test]
+    fn debug_nonce_checks_forgets_old_nonces() -> Result<()> {
+        // Split keys have no one-time key fingerprints remembering the evicted nonces
+        let cipher = ChaCha20Poly1305::with_split_keys(&[0x33; 32], &[0x44; 32]);
+
+        for i in 0..=chacha20poly1305::NONCE_LOG_SIZE {
+            let mut nonce: Nonce = [0; 12];
+            nonce[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            cipher.encrypt_in_place_detached(&nonce, b"", &mut [])?;
+        }
+
+        // The nonce 0 has been evicted by the later ones
+        cipher.encrypt_in_place_detached(&[0; 12], b"", &mut [])?;
+        Ok(())
+    }
+
+    #[cfg(all(feature = "debug-nonce-checks", feature = "alloc", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "one-time Poly1305 key reused")]
+    fn debug_nonce_checks_catches_one_time_key_reuse() {
+        let cipher = ChaCha20Poly1305::new(&[0x33; 32]);
+
+        // The nonce 0 leaves the nonce log, but its one-time key is still remembered
+        for i in 0..=chacha20poly1305::NONCE_LOG_SIZE {
+            let mut nonce: Nonce = [0; 12];
+            nonce[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            cipher
+                .encrypt_in_place_detached(&nonce, b"", &mut [])
+                .unwrap();
+        }
+
+        let _ = cipher.encrypt_in_place_detached(&[0; 12], b"", &mut []);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn with_split_keys() -> Result<()> {
+        let key: Key = [0x42; 32];
+        let mac_key: Poly1305Key = [0x24; 32];
+        let nonce: Nonce = [0x07; 12];
+        let plaintext = [0x5a; 100];
+        let cipher = ChaCha20Poly1305::with_split_keys(&key, &mac_key);
+
+        let sealed = cipher.encrypt(&nonce, b"aad", &plaintext)?;
+        assert_eq!(cipher.decrypt(&nonce, b"aad", &sealed)?, plaintext);
+        assert_eq!(
+            cipher.decrypt(&nonce, b"other", &sealed).unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+
+        // The data is encrypted from the counter 0 and authenticated with the MAC key as is
+        let (ciphertext, tag) = sealed.split_at(plaintext.len());
+        let mut expected = plaintext;
+        ChaCha20::new(&key, &nonce).perform_in_place(&mut expected)?;
+        assert_eq!(ciphertext, expected);
+
+        let mut poly1305 = Poly1305::new(&mac_key);
+        poly1305.update(b"aad");
+        poly1305.update_leftover_pad16();
+        poly1305.update(ciphertext);
+        poly1305.update_leftover_pad16();
+        poly1305.update(&3u64.to_le_bytes());
+        poly1305.update(&100u64.to_le_bytes());
+        assert_eq!(poly1305.finalize(), tag);
+
+        let standard = ChaCha20Poly1305::new(&key);
+        let standard_sealed = standard.encrypt(&nonce, b"aad", &plaintext)?;
+        assert_ne!(sealed, standard_sealed);
+        assert_eq!(
+            standard.decrypt(&nonce, b"aad", &sealed).unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decrypt_too_short() -> Result<()> {
+        let cipher = ChaCha20Poly1305::new(&[0x16; 32]);
+        let nonce: Nonce = [0x61; 12];
+
+        for len in [0, 5, 15] {
+            assert_eq!(
+                cipher.decrypt(&nonce, b"", &[0; 15][..len]).unwrap_err(),
+                error::DecryptError::Unauthenticated
+            );
+        }
+
+        // A message with an empty plaintext is exactly one tag long
+        let sealed = cipher.encrypt(&nonce, b"", b"")?;
+        assert_eq!(sealed.len(), 16);
+        assert_eq!(cipher.decrypt(&nonce, b"", &sealed)?, b"");
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_decrypt_to_slice() -> Result<()> {
+        let key: Key = [0x54; 32];
+        let cipher = ChaCha20Poly1305::new(&key);
+        let nonce: Nonce = [0x45; 12];
+        let text = b"heapless";
+
+        let mut sealed = [0; 40];
+        let written = cipher.encrypt_to_slice(&nonce, b"aad", text, &mut sealed)?;
+        assert_eq!(written, text.len() + 16);
+        assert!(sealed[written..].iter().all(|&b| b == 0));
+
+        let mut detached = *text;
+        let tag =
+            ChaCha20Poly1305::new(&key).encrypt_in_place_detached(&nonce, b"aad", &mut detached)?;
+        assert_eq!(sealed[..8], detached);
+        assert_eq!(sealed[8..24], tag);
+
+        let mut plaintext = [0; 8];
+        assert_eq!(
+            cipher.decrypt_to_slice(&nonce, b"aad", &sealed[..written], &mut plaintext)?,
+            8
+        );
+        assert_eq!(&plaintext, text);
+
+        assert_eq!(
+            cipher
+                .encrypt_to_slice(&nonce, b"aad", text, &mut [0; 23])
+                .unwrap_err(),
+            error::Error::InvalidLength
+        );
+        assert_eq!(
+            cipher
+                .decrypt_to_slice(&nonce, b"aad", &sealed[..written], &mut [0; 7])
+                .unwrap_err(),
+            error::Error::InvalidLength
+        );
+        assert_eq!(
+            cipher
+                .decrypt_to_slice(&nonce, b"aad", &sealed[..15], &mut plaintext)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        sealed[0] ^= 0x01;
+        assert_eq!(
+            cipher
+                .decrypt_to_slice(&nonce, b"aad", &sealed[..written], &mut plaintext)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn associated_sizes() {
+        assert_eq!(ChaCha20::KEY_SIZE, 32);
+        assert_eq!(ChaCha20::NONCE_SIZE, 12);
+        assert_eq!(ChaCha20::BLOCK_SIZE, 64);
+        assert_eq!(Poly1305::KEY_SIZE, 32);
+        assert_eq!(Poly1305::TAG_SIZE, 16);
+        assert_eq!(Poly1305::BLOCK_SIZE, 16);
+        assert_eq!(ChaCha20Poly1305::KEY_SIZE, 32);
+        assert_eq!(ChaCha20Poly1305::NONCE_SIZE, 12);
+        assert_eq!(ChaCha20Poly1305::TAG_SIZE, 16);
+    }
+
+    #[test]
+    fn length_limits() {
+        let max_plaintext = u128::from(super::MAX_PLAINTEXT_LEN);
+        let max_aad = u128::from(super::MAX_AAD_LEN);
+
+        assert_eq!(max_plaintext, 274_877_906_880);
+        assert!(check_lengths(max_aad, max_plaintext).is_ok());
+        assert_eq!(
+            check_lengths(0, max_plaintext + 1).unwrap_err(),
+            error::Error::DataTooLong
+        );
+        assert_eq!(
+            check_lengths(max_aad + 1, 0).unwrap_err(),
+            error::Error::AadTooLong
+        );
+        assert_eq!(
+            check_lengths(u128::MAX, u128::MAX).unwrap_err(),
+            error::Error::AadTooLong
+        );
+
+        // The keystream runs out exactly at the limit
+        let mut chacha20 = ChaCha20::new_with_cnt(&[0; 32], &[0; 12], u32::MAX);
+        assert!(chacha20.perform_in_place(&mut [0; 64]).is_ok());
+        assert_eq!(
+            chacha20.perform_in_place(&mut [0; 1]).unwrap_err(),
+            error::Error::DataTooLong
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn dump_vector_rfc_8439() -> Result<()> {
+        let key: Key = core::array::from_fn(|i| 0x80 + i as u8);
+        let nonce: Nonce = [
+            0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+        ];
+        let aad = [
+            0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+        ];
+        let text = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let dump = super::dump_vector(&key, &nonce, &aad, text)?;
+
+        assert!(dump.contains("nonce: 070000004041424344454647\n"));
+        assert!(dump.contains("aad: 50515253c0c1c2c3c4c5c6c7\n"));
+        assert!(dump.contains("ciphertext: d31a8d34648e60db7b86afbc53ef7ec2"));
+        assert!(dump.contains("0b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc3ff4def08e4b7a9de576d26586cec64b6116\n"));
+        assert!(dump.ends_with("tag: 1ae10b594f09e26a7e902ecbd0600691\n"));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "alloc", feature = "subtle"))]
+    #[test]
+    fn open_ct_choice() -> Result<()> {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+        let aad = b"header";
+        let text = b"constant time opening";
+
+        let (mut sealed, tag) = AeadState::new(&key, &nonce, aad)?.encrypt_oneshot(text)?;
+        sealed.extend_from_slice(&tag);
+
+        let cipher = ChaCha20Poly1305::new(&key);
+
+        let (valid, plaintext) = cipher.open_ct(&nonce, aad, &sealed);
+        assert_eq!(valid.unwrap_u8(), 1);
+        assert_eq!(plaintext, text);
+
+        let last = sealed.len() - 1;
+        sealed[last] ^= 0x01;
+
+        let (valid, plaintext) = cipher.open_ct(&nonce, aad, &sealed);
+        assert_eq!(valid.unwrap_u8(), 0);
+        assert_eq!(plaintext.len(), text.len());
+        assert!(plaintext.iter().all(|&v| v == 0));
+
+        let (valid, plaintext) = cipher.open_ct(&nonce, aad, &sealed[..5]);
+        assert_eq!(valid.unwrap_u8(), 0);
+        assert!(plaintext.is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn detached_matches_combined() -> Result<()> {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+        let aad = b"header";
+        let text = b"detached tag must match the combined form";
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let combined = cipher.encrypt(&nonce, aad, text)?;
+        let (ciphertext, tag) = ChaCha20Poly1305::new(&key).encrypt_detached(&nonce, aad, text)?;
+
+        assert_eq!(combined.len(), text.len() + 16);
+        assert_eq!(&combined[..text.len()], ciphertext.as_slice());
+        assert_eq!(&combined[text.len()..], tag.as_slice());
+
+        assert_eq!(cipher.decrypt(&nonce, aad, &combined)?, text);
+        assert_eq!(
+            cipher.decrypt_detached(&nonce, aad, &ciphertext, &tag)?,
+            text
+        );
+
+        let mut bad_tag = tag;
+        bad_tag[0] ^= 0x01;
+        assert_eq!(
+            cipher
+                .decrypt_detached(&nonce, aad, &ciphertext, &bad_tag)
+                .unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn in_place_detached() -> Result<()> {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+        let aad = b"header";
+        let text = b"in-place detached encryption without any heap";
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let mut buffer = *text;
+        let tag = cipher.encrypt_in_place_detached(&nonce, aad, &mut buffer)?;
+        assert_ne!(&buffer, text);
+
+        let ciphertext = buffer;
+        let mut bad_tag = tag;
+        bad_tag[15] ^= 0x80;
+
+        assert_eq!(
+            cipher
+                .decrypt_in_place_detached(&nonce, aad, &mut buffer, &bad_tag)
+                .unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+        assert_eq!(buffer, ciphertext);
+
+        cipher.decrypt_in_place_detached(&nonce, aad, &mut buffer, &tag)?;
+        assert_eq!(&buffer, text);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn mac_then_encrypt_round_trip() -> Result<()> {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+        let aad = b"header";
+        let text = b"legacy mac-then-encrypt message";
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let mut sealed = cipher.mac_then_encrypt(&nonce, aad, text)?;
+        assert_eq!(sealed.len(), text.len() + 16);
+        assert_ne!(
+            sealed,
+            ChaCha20Poly1305::new(&key).encrypt(&nonce, aad, text)?
+        );
+
+        assert_eq!(cipher.decrypt_then_verify(&nonce, aad, &sealed)?, text);
+        assert_eq!(
+            cipher
+                .decrypt_then_verify(&nonce, b"other", &sealed)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        sealed[3] ^= 0x01;
+        assert_eq!(
+            cipher
+                .decrypt_then_verify(&nonce, aad, &sealed)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+        assert_eq!(
+            cipher
+                .decrypt_then_verify(&nonce, aad, &sealed[..5])
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_length_committed() -> Result<()> {
+        let key: Key = [0x1c; 32];
+        let nonce: Nonce = [0xc1; 12];
+        let text = b"length committed message";
+        let cipher = ChaCha20Poly1305::new(&key);
+
+        let sealed = cipher.encrypt_length_committed(&nonce, b"aad", text)?;
+        assert_eq!(
+            cipher.decrypt_length_committed(&nonce, b"aad", &sealed)?,
+            text
+        );
+
+        let mut aad = (text.len() as u64).to_le_bytes().to_vec();
+        aad.extend_from_slice(b"aad");
+        assert_eq!(cipher.decrypt(&nonce, &aad, &sealed)?, text);
+
+        // A shorter ciphertext keeping the original tag
+        let split_at = sealed.len() - 16;
+        let mut forged = sealed[..split_at - 4].to_vec();
+        forged.extend_from_slice(&sealed[split_at..]);
+        assert_eq!(
+            cipher
+                .decrypt_length_committed(&nonce, b"aad", &forged)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        for len in [0, 15] {
+            assert_eq!(
+                cipher
+                    .decrypt_length_committed(&nonce, b"aad", &sealed[..len])
+                    .unwrap_err(),
+                error::Error::Unauthenticated
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_with_tag_len() -> Result<()> {
+        let key: Key = [0x7a; 32];
+        let nonce: Nonce = [0xa7; 12];
+        let text = b"sensor reading";
+        let cipher = ChaCha20Poly1305::new(&key);
+
+        let sealed = cipher.encrypt_with_tag_len(&nonce, b"aad", text, 8)?;
+        assert_eq!(sealed.len(), text.len() + 8);
+        assert_eq!(
+            cipher.decrypt_with_tag_len(&nonce, b"aad", &sealed, 8)?,
+            text
+        );
+
+        let mut forged = sealed.clone();
+        forged[text.len() + 7] ^= 0x01;
+        assert_eq!(
+            cipher
+                .decrypt_with_tag_len(&nonce, b"aad", &forged, 8)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        // Truncating a 16 bytes tag down to 8 bytes does not verify
+        let full = ChaCha20Poly1305::new(&key).encrypt_with_tag_len(&nonce, b"aad", text, 16)?;
+        assert_eq!(
+            cipher.decrypt_with_tag_len(&nonce, b"aad", &full, 16)?,
+            text
+        );
+        assert_eq!(
+            cipher
+                .decrypt_with_tag_len(&nonce, b"aad", &full[..text.len() + 8], 8)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+        // Nor does the standard AEAD accept it
+        assert_eq!(
+            cipher.decrypt(&nonce, b"aad", &full).unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+
+        for tag_len in [0, 17] {
+            assert_eq!(
+                cipher
+                    .encrypt_with_tag_len(&[0; 12], b"aad", text, tag_len)
+                    .unwrap_err(),
+                error::Error::InvalidLength
+            );
+            assert_eq!(
+                cipher
+                    .decrypt_with_tag_len(&nonce, b"aad", &full, tag_len)
+                    .unwrap_err(),
+                error::Error::InvalidLength
+            );
+        }
+        assert_eq!(
+            cipher
+                .decrypt_with_tag_len(&nonce, b"aad", &sealed[..7], 8)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_with_context_separates_domains() -> Result<()> {
+        let key: Key = [0x17; 32];
+        let nonce: Nonce = [0x71; 12];
+        let aad = b"header";
+        let text = b"message of type A";
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let sealed = cipher.encrypt_with_context(&nonce, b"A", aad, text)?;
+
+        assert_eq!(
+            cipher.decrypt_with_context(&nonce, b"A", aad, &sealed)?,
+            text
+        );
+        assert_eq!(
+            cipher
+                .decrypt_with_context(&nonce, b"B", aad, &sealed)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+        // Moving bytes between the context and the AAD changes the length prefix
+        assert_eq!(
+            cipher
+                .decrypt_with_context(&nonce, b"", b"Aheader", &sealed)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_fingerprint_identifies_the_key() {
+        let cipher = ChaCha20Poly1305::new(&[0x01; 32]);
+        let other = ChaCha20Poly1305::new(&[0x02; 32]);
+
+        assert_eq!(cipher.key_fingerprint(), cipher.key_fingerprint());
+        assert_eq!(
+            cipher.key_fingerprint(),
+            ChaCha20Poly1305::new(&[0x01; 32]).key_fingerprint()
+        );
+        assert_ne!(cipher.key_fingerprint(), other.key_fingerprint());
+        assert_ne!(cipher.key_fingerprint(), [0x01; 8]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn key_fingerprint_is_not_in_the_aead_output() -> Result<()> {
+        let key = [0x01; 32];
+        let fingerprint = ChaCha20Poly1305::new(&key).key_fingerprint();
+
+        // The nonce the fingerprint used to be derived from, and the nonce and counter words of
+        // the domain separation input
+        let domain_nonce: Nonce = super::FINGERPRINT_DOMAIN[4..].try_into().unwrap();
+        for nonce in [*b"key-finger-p", domain_nonce] {
+            let one_time_key = ChaCha20Poly1305::poly1305_key(&key, &nonce);
+            assert!(one_time_key.windows(8).all(|w| w != fingerprint));
+
+            let sealed = ChaCha20Poly1305::new(&key).encrypt(&nonce, b"", &[0; 1024])?;
+            assert!(sealed.windows(8).all(|w| w != fingerprint));
+
+            let mut keystream = [0; 256];
+            ChaCha20::new(&key, &nonce).fill_keystream(&mut keystream)?;
+            assert!(keystream.windows(8).all(|w| w != fingerprint));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_nonces_unique_finds_duplicate() {
+        let nonces: [Nonce; 6] = [[5; 12], [1; 12], [4; 12], [1; 12], [2; 12], [5; 12]];
+        assert_eq!(check_nonces_unique(&nonces), Err(3));
+
+        let nonces: [Nonce; 4] = [[3; 12], [1; 12], [4; 12], [2; 12]];
+        assert_eq!(check_nonces_unique(&nonces), Ok(()));
+        assert_eq!(check_nonces_unique(&[]), Ok(()));
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn generate_key_and_nonce() -> Result<()> {
+        let mut rng = crate::chacha20::test::TestRng(0xda3e39cb94b95bdb);
+
+        let key = ChaCha20Poly1305::generate_key(&mut rng);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut rng);
+        assert_ne!(key, ChaCha20Poly1305::generate_key(&mut rng));
+        assert_ne!(nonce, ChaCha20Poly1305::generate_nonce(&mut rng));
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let mut data = *b"random keys";
+        let tag = cipher.encrypt_in_place_detached(&nonce, b"", &mut data)?;
+        cipher.decrypt_in_place_detached(&nonce, b"", &mut data, &tag)?;
+        assert_eq!(&data, b"random keys");
+
+        Ok(())
+    }
+
+    #[test]
+    fn encryptor_matches_oneshot() -> Result<()> {
+        let key: Key = [0x11; 32];
+        let nonce: Nonce = [0x22; 12];
+        let aad = b"additional data spread over several calls";
+        let text = b"a plaintext that is encrypted in several parts of various sizes";
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let mut expected = *text;
+        let expected_tag = cipher.encrypt_in_place_detached(&nonce, aad, &mut expected)?;
+
+        let mut data = *text;
+        let mut encryptor = ChaCha20Poly1305::new(&key).encryptor(&nonce);
+        encryptor.update_aad(&aad[..5])?;
+        encryptor.update_aad(&aad[5..])?;
+        let (first, rest) = data.split_at_mut(7);
+        encryptor.update(first)?;
+        encryptor.update(&mut rest[..30])?;
+        encryptor.update(&mut rest[30..])?;
+
+        assert_eq!(
+            encryptor.update_aad(b"late").unwrap_err(),
+            error::Error::AadAfterData
+        );
+        assert_eq!(encryptor.finalize(), expected_tag);
+        assert_eq!(data, expected);
+
+        // AAD only
+        let mut encryptor = ChaCha20Poly1305::new(&key).encryptor(&nonce);
+        encryptor.update_aad(aad)?;
+        assert_eq!(
+            encryptor.finalize(),
+            ChaCha20Poly1305::new(&key).encrypt_in_place_detached(&nonce, aad, &mut [])?
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn seal_with_plaintext_tag() -> Result<()> {
+        let key: Key = [0x5c; 32];
+        let nonce: Nonce = [0xc5; 12];
+        let aad = b"backup header";
+        let text = b"the data being backed up";
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let (sealed, plaintext_tag) = cipher.seal_with_plaintext_tag(&nonce, aad, text)?;
+        assert_eq!(
+            sealed,
+            ChaCha20Poly1305::new(&key).encrypt(&nonce, aad, text)?
+        );
+
+        let restored = cipher.decrypt(&nonce, aad, &sealed)?;
+        assert!(cipher.verify_plaintext_tag(&nonce, &restored, &plaintext_tag));
+        assert!(!cipher.verify_plaintext_tag(&nonce, &restored[1..], &plaintext_tag));
+        assert!(!cipher.verify_plaintext_tag(&[0; 12], &restored, &plaintext_tag));
+        // The plaintext tag is not the AEAD tag over the plaintext
+        assert_ne!(plaintext_tag[..], sealed[text.len()..]);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn aad_and_ciphertext_from_one_buffer() -> Result<()> {
+        let key: Key = [0x33; 32];
+        let nonce: Nonce = [0x44; 12];
+        let header = [0xab; 32];
+        let text = b"payload transmitted right after its header";
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let sealed = cipher.encrypt(&nonce, &header, text)?;
+
+        let mut buf = [0u8; 32 + 42 + 16];
+        buf[..32].copy_from_slice(&header);
+        buf[32..].copy_from_slice(&sealed);
+
+        assert_eq!(cipher.decrypt(&nonce, &buf[..32], &buf[32..])?, text);
+        // overlapping slices are fine as well since both are only read
+        assert_eq!(
+            cipher.decrypt(&nonce, &buf[..40], &buf[32..]).unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+        assert_eq!(buf[..32], header);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Runs the vectors of a file in the Project Wycheproof `AeadTest` schema. Groups with a nonce
+    /// or tag size the AEAD does not support are skipped.
+    ///
+    /// The `acceptable` cases may either be opened to their message or be rejected as
+    /// unauthenticated.
+    #[cfg(feature = "alloc")]
+    fn run_wycheproof_vectors(json: &str) -> usize {
+        let doc: serde_json::Value = serde_json::from_str(json).unwrap();
+        let mut count = 0;
+
+        for group in doc["testGroups"].as_array().unwrap() {
+            if group["ivSize"] != 96 || group["tagSize"] != 128 {
+                continue;
+            }
+
+            for test in group["tests"].as_array().unwrap() {
+                let id = &test["tcId"];
+                let comment = test["comment"].as_str().unwrap();
+                let field = |name: &str| from_hex(test[name].as_str().unwrap());
+
+                let key: Key = field("key").try_into().unwrap();
+                let nonce: Nonce = field("iv").try_into().unwrap();
+                let (aad, msg) = (field("aad"), field("msg"));
+                let mut sealed = field("ct");
+                sealed.extend_from_slice(&field("tag"));
+
+                let cipher = ChaCha20Poly1305::new(&key);
+
+                match test["result"].as_str().unwrap() {
+                    "valid" => {
+                        assert_eq!(
+                            cipher.decrypt(&nonce, &aad, &sealed),
+                            Ok(msg.clone()),
+                            "tcId {id} ({comment})"
+                        );
+                        assert_eq!(
+                            cipher.encrypt(&nonce, &aad, &msg),
+                            Ok(sealed),
+                            "tcId {id} ({comment})"
+                        );
+                    }
+                    "acceptable" => match cipher.decrypt(&nonce, &aad, &sealed) {
+                        Ok(opened) => assert_eq!(opened, msg, "tcId {id} ({comment})"),
+                        Err(err) => assert_eq!(
+                            err,
+                            error::DecryptError::Unauthenticated,
+                            "tcId {id} ({comment})"
+                        ),
+                    },
+                    "invalid" => assert_eq!(
+                        cipher.decrypt(&nonce, &aad, &sealed),
+                        Err(error::DecryptError::Unauthenticated),
+                        "tcId {id} ({comment})"
+                    ),
+                    result => panic!("tcId {id} ({comment}): unknown result {result}"),
+                }
+
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn wycheproof_vectors() {
+        let json = include_str!("../testdata/wycheproof_chacha20_poly1305.json");
+        assert_eq!(run_wycheproof_vectors(json), 293);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn boringssl_vectors() {
+        let json = include_str!("../testdata/boringssl_chacha20_poly1305.json");
+        assert_eq!(run_wycheproof_vectors(json), 66);
+    }
+
+    /// Flips single bits of the tag, the ciphertext and the aad of the valid BoringSSL vectors,
+    /// which must then all be rejected.
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn bit_flipped_boringssl_vectors() {
+        let json = include_str!("../testdata/boringssl_chacha20_poly1305.json");
+        let doc: serde_json::Value = serde_json::from_str(json).unwrap();
+        let mut count = 0;
+
+        for test in doc["testGroups"][0]["tests"].as_array().unwrap() {
+            let id = &test["tcId"];
+            let field = |name: &str| from_hex(test[name].as_str().unwrap());
+
+            let key: Key = field("key").try_into().unwrap();
+            let nonce: Nonce = field("iv").try_into().unwrap();
+            let aad = field("aad");
+            let mut sealed = field("ct");
+            sealed.extend_from_slice(&field("tag"));
+
+            let cipher = ChaCha20Poly1305::new(&key);
+            let ct_len = sealed.len() - ChaCha20Poly1305::TAG_SIZE;
+            let assert_rejected = |aad: &[u8], sealed: &[u8], what: &str| {
+                assert_eq!(
+                    cipher.decrypt(&nonce, aad, sealed),
+                    Err(error::DecryptError::Unauthenticated),
+                    "tcId {id}: {what}"
+                );
+            };
+
+            for (byte, bit) in [(ct_len, 0), (sealed.len() - 1, 7)] {
+                let mut flipped = sealed.clone();
+                flipped[byte] ^= 1 << bit;
+                assert_rejected(&aad, &flipped, "flipped bit of the tag");
+                count += 1;
+            }
+
+            if ct_len != 0 {
+                let mut flipped = sealed.clone();
+                flipped[0] ^= 1;
+                assert_rejected(&aad, &flipped, "flipped bit of the ciphertext");
+                count += 1;
+            }
+
+            if !aad.is_empty() {
+                let mut flipped = aad.clone();
+                flipped[0] ^= 1;
+                assert_rejected(&flipped, &sealed, "flipped bit of the aad");
+                count += 1;
+            }
+        }
+
+        assert_eq!(count, 262);
+    }
+
+    #[test]
+    fn cipher_for_record() -> Result<()> {
+        let key: Key = [0x61; 32];
+        let base_nonce: Nonce = [0x16; 12];
+        let cipher = ChaCha20Poly1305::new(&key);
+
+        let mut nonce = base_nonce;
+        nonce[11] ^= 0x01;
+        nonce[4] ^= 0x02;
+        let mut expected = [0u8; 256];
+        ChaCha20::new_with_cnt(&key, &nonce, 1).fill_keystream(&mut expected)?;
+
+        let mut first = [0u8; 256];
+        let mut second = [0u8; 256];
+        cipher
+            .cipher_for_record(&base_nonce, 0)?
+            .fill_keystream(&mut first)?;
+        cipher
+            .cipher_for_record(&base_nonce, 0x0200_0000_0000_0001)?
+            .fill_keystream(&mut second)?;
+
+        assert_eq!(second, expected);
+        assert!(first
+            .chunks_exact(64)
+            .all(|block| second.chunks_exact(64).all(|other| block != other)));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decrypt_detached_verifies_first() -> Result<()> {
+        let key: Key = [0x63; 32];
+        let nonce: Nonce = [0x36; 12];
+        let aad = b"socket";
+        let text = b"read from the wire in one go";
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let (mut ciphertext, mut tag) = cipher.encrypt_detached(&nonce, aad, text)?;
+        assert_eq!(
+            cipher.decrypt_detached(&nonce, aad, &ciphertext, &tag)?,
+            text
+        );
+
+        tag[0] ^= 0x01;
+        assert_eq!(
+            cipher
+                .decrypt_detached(&nonce, aad, &ciphertext, &tag)
+                .unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+
+        tag[0] ^= 0x01;
+        ciphertext[text.len() - 1] ^= 0x80;
+        assert_eq!(
+            cipher
+                .decrypt_detached(&nonce, aad, &ciphertext, &tag)
+                .unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+        assert_eq!(
+            cipher
+                .decrypt_detached(&nonce, b"", &ciphertext, &tag)
+                .unwrap_err(),
+            error::DecryptError::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_with_scratch() -> Result<()> {
+        let key: Key = [0x3e; 32];
+        let nonce: Nonce = [0xe3; 12];
+        let mut scratch = [0xaa; 64];
+
+        for len in [0, 1, 64, 100] {
+            let text = [0x5c; 100];
+            assert_eq!(
+                ChaCha20Poly1305::new(&key).encrypt_with_scratch(
+                    &nonce,
+                    b"aad",
+                    &text[..len],
+                    &mut scratch
+                )?,
+                ChaCha20Poly1305::new(&key).encrypt(&nonce, b"aad", &text[..len])?
+            );
+            assert_eq!(scratch, [0; 64]);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn seal_with_content_id() -> Result<()> {
+        let key: Key = [0x0c; 32];
+        let nonce: Nonce = [0xc0; 12];
+        let cipher = ChaCha20Poly1305::new(&key);
+
+        let (sealed, id) = cipher.seal_with_content_id(&nonce, b"blob", b"same content")?;
+        let (sealed_again, id_again) =
+            ChaCha20Poly1305::new(&key).seal_with_content_id(&nonce, b"blob", b"same content")?;
+
+        assert_eq!(sealed, sealed_again);
+        assert_eq!(id, id_again);
+        assert_eq!(
+            sealed,
+            ChaCha20Poly1305::new(&key).encrypt(&nonce, b"blob", b"same content")?
+        );
+
+        let (_, other_id) =
+            ChaCha20Poly1305::new(&key).seal_with_content_id(&nonce, b"blob", b"some content")?;
+        assert_ne!(id, other_id);
+        let (_, other_id) = cipher.seal_with_content_id(&[0xc1; 12], b"blob", b"same content")?;
+        assert_ne!(id, other_id);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn encrypt_with_trailer() -> Result<()> {
+        let key: Key = [0x29; 32];
+        let nonce: Nonce = [0x92; 12];
+        let aad = b"packet header";
+        let text = b"packet payload";
+        let crc = [0xde, 0xad, 0xbe, 0xef];
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let mut sealed = cipher.encrypt_with_trailer(&nonce, aad, text, &crc)?;
+
+        assert_eq!(sealed.len(), text.len() + crc.len() + 16);
+        assert_eq!(sealed[text.len()..text.len() + 4], crc);
+        assert_eq!(
+            sealed[..text.len()],
+            ChaCha20Poly1305::new(&key).encrypt(&nonce, aad, text)?[..text.len()]
+        );
+        assert_eq!(cipher.decrypt_with_trailer(&nonce, aad, &sealed, 4)?, text);
+
+        sealed[text.len() + 3] ^= 0x01;
+        assert_eq!(
+            cipher
+                .decrypt_with_trailer(&nonce, aad, &sealed, 4)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+        assert_eq!(
+            cipher
+                .decrypt_with_trailer(&nonce, aad, &sealed[..10], 4)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+        assert_eq!(
+            cipher
+                .decrypt_with_trailer(&nonce, aad, &sealed, usize::MAX)
+                .unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn aead_authenticates_the_rfc_framing() -> Result<()> {
+        extern crate std;
+        use super::Mac;
+        use alloc::vec::Vec;
+        use core::cell::RefCell;
+
+        std::thread_local! {
+            static CALLS: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+        }
+
+        /// Records its key and every `update` call, the padding being a call of its own
+        struct Recorder {
+            len: usize,
+        }
+
+        impl Mac for Recorder {
+            fn new(key: &Poly1305Key) -> Self {
+                CALLS.with_borrow_mut(|calls| calls.push(key.to_vec()));
+                Self { len: 0 }
+            }
+
+            fn update(&mut self, data: &[u8]) {
+                self.len += data.len();
+                CALLS.with_borrow_mut(|calls| calls.push(data.to_vec()));
+            }
+
+            fn update_leftover_pad16(&mut self) {
+                let padding = (16 - self.len % 16) % 16;
+                if padding != 0 {
+                    self.update(&[0; 16][..padding]);
+                }
+            }
+
+            fn finalize(self) -> Tag {
+                [0x5a; 16]
+            }
+        }
+
+        let key = [0x42; 32];
+        let nonce = [0x07; 12];
+        let aad = b"twelve bytes";
+        let text = [0x17; 100];
+        let one_time_key = gen_poly1305_key(&mut ChaCha20::new(&key, &nonce));
+        let (ciphertext, _) = ChaCha20Poly1305::new(&key).encrypt_detached(&nonce, aad, &text)?;
+
+        let cipher = ChaCha20Poly1305::new(&key);
+        let mut data = text;
+        let mut state = cipher.with_mac::<Recorder>(&nonce, aad)?;
+        state.encrypt_in_place(&mut data[..60])?;
+        state.encrypt_in_place(&mut data[60..])?;
+        assert_eq!(state.finalize(), [0x5a; 16]);
+        assert_eq!(data[..], ciphertext[..]);
+
+        let calls = CALLS.take();
+        let expected: [&[u8]; 8] = [
+            &one_time_key,
+            aad,
+            &[0; 4],
+            &ciphertext[..60],
+            &ciphertext[60..],
+            &[0; 12],
+            &12u64.to_le_bytes(),
+            &100u64.to_le_bytes(),
+        ];
+        assert_eq!(calls, expected);
+
+        // The decryption authenticates the same sequence, with the ciphertext in one call
+        let state = cipher.with_mac::<Recorder>(&nonce, aad)?;
+        state.decrypt_oneshot_in_place(&mut data, &[0x5a; 16])?;
+        assert_eq!(data, text);
+
+        let calls = CALLS.take();
+        let expected: [&[u8]; 7] = [
+            &one_time_key,
+            aad,
+            &[0; 4],
+            &ciphertext,
+            &[0; 12],
+            &12u64.to_le_bytes(),
+            &100u64.to_le_bytes(),
+        ];
+        assert_eq!(calls, expected);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn alloc_size_matches_the_sealed_length() -> Result<()> {
+        let cipher = ChaCha20Poly1305::new(&[0x33; 32]);
+
+        for len in [0, 1, 15, 16, 17, 64, 1000] {
+            let plaintext = [0xa5; 1000];
+            let sealed = cipher.encrypt(&[len as u8; 12], b"aad", &plaintext[..len])?;
+
+            assert_eq!(ChaCha20Poly1305::encrypt_alloc_size(len)?, sealed.len());
+            assert_eq!(ChaCha20Poly1305::decrypt_alloc_size(sealed.len())?, len);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn alloc_size_edge_cases() {
+        assert_eq!(
+            ChaCha20Poly1305::encrypt_alloc_size(usize::MAX - 16),
+            Ok(usize::MAX)
+        );
+        assert_eq!(
+            ChaCha20Poly1305::encrypt_alloc_size(usize::MAX - 15),
+            Err(error::Error::DataTooLong)
+        );
+        assert_eq!(
+            ChaCha20Poly1305::encrypt_alloc_size(usize::MAX),
+            Err(error::Error::DataTooLong)
+        );
+
+        assert_eq!(ChaCha20Poly1305::decrypt_alloc_size(16), Ok(0));
+        for len in [0, 1, 15] {
+            assert_eq!(
+                ChaCha20Poly1305::decrypt_alloc_size(len),
+                Err(error::Error::Unauthenticated)
+            );
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn stored_key_derives_one_time_key_from_block_0() -> Result<()> {
+        let key = [0x42; 32];
+        let cipher = ChaCha20Poly1305::new(&key);
+        let aad = b"header";
+        let plaintext = [0x17; 100];
+
+        for nonce in [[0; 12], [1; 12], [0xff; 12]] {
+            let (ciphertext, tag) = cipher.encrypt_detached(&nonce, aad, &plaintext)?;
+
+            let mut block_0 = [0u8; 64];
+            ChaCha20::new(&key, &nonce).perform_in_place(&mut block_0)?;
+            let mut one_time_key = [0u8; 32];
+            one_time_key.copy_from_slice(&block_0[..32]);
+
+            let mut expected = plaintext;
+            ChaCha20::new_with_cnt(&key, &nonce, 1).perform_in_place(&mut expected)?;
+            assert_eq!(ciphertext, expected);
+
+            let mut poly = Poly1305::new(&one_time_key);
+            poly.update(aad);
+            poly.update_leftover_pad16();
+            poly.update(&ciphertext);
+            poly.update_leftover_pad16();
+            poly.update(&(aad.len() as u64).to_le_bytes());
+            poly.update(&(ciphertext.len() as u64).to_le_bytes());
+            assert_eq!(tag, poly.finalize());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn stored_key_is_zeroized_on_drop() {
+        fn assert_zeroize_on_drop<T: zeroize::ZeroizeOnDrop>() {}
+        assert_zeroize_on_drop::<ChaCha20Poly1305>();
+
+        let mut cipher = core::mem::ManuallyDrop::new(ChaCha20Poly1305::with_split_keys(
+            &[0x11; 32],
+            &[0x22; 32],
+        ));
+
+        // SAFETY: the cipher is never used or dropped again, only its key bytes are read back
+        unsafe { core::ptr::drop_in_place(&mut *cipher) };
+        assert_eq!(cipher.key, [0; 32]);
+        assert_eq!(cipher.mac_key, Some([0; 32]));
+    }
+
+    #[test]
+    fn derive_session() -> Result<()> {
+        let master = [0x4d; 32];
+        let session = ChaCha20Poly1305::derive_session(&master, b"session-0001")?;
+
+        let mut input = [0; 16];
+        input[..12].copy_from_slice(b"session-0001");
+        let expected = ChaCha20Poly1305::new(&chacha20::hchacha20(&master, &input));
+        assert_eq!(session.key_fingerprint(), expected.key_fingerprint());
+
+        // Deterministic, and separated per session and from the master key
+        let again = ChaCha20Poly1305::derive_session(&master, b"session-0001")?;
+        let other = ChaCha20Poly1305::derive_session(&master, b"session-0002")?;
+        assert_eq!(session.key_fingerprint(), again.key_fingerprint());
+        assert_ne!(session.key_fingerprint(), other.key_fingerprint());
+        assert_ne!(
+            session.key_fingerprint(),
+            ChaCha20Poly1305::new(&master).key_fingerprint()
+        );
+
+        let other_master = ChaCha20Poly1305::derive_session(&[0x4e; 32], b"session-0001")?;
+        assert_ne!(session.key_fingerprint(), other_master.key_fingerprint());
+
+        assert!(ChaCha20Poly1305::derive_session(&master, &[0x11; 16]).is_ok());
+        assert_eq!(
+            ChaCha20Poly1305::derive_session(&master, &[0x11; 17]).err(),
+            Some(error::Error::InvalidLength)
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn verify_roundtrip() -> Result<()> {
+        let cipher = ChaCha20Poly1305::new(&[0x6d; 32]);
+        let nonce = [0x2e; 12];
+
+        for len in [0, 1, 64, 200] {
+            let nonce = [len as u8; 12];
+            let sealed = cipher.encrypt(&nonce, b"aad", &[0x3a; 200][..len])?;
+            assert_eq!(cipher.verify_roundtrip(&nonce, b"aad", &sealed), Ok(true));
+        }
+
+        // Anything `decrypt` rejects is rejected the same way
+        let mut sealed = cipher.encrypt(&nonce, b"aad", b"stored block")?;
+        sealed[3] ^= 0x10;
+        assert_eq!(
+            cipher.verify_roundtrip(&nonce, b"aad", &sealed),
+            Err(error::Error::Unauthenticated)
+        );
+        assert_eq!(
+            cipher.verify_roundtrip(&nonce, b"aad", &sealed[..15]),
+            Err(error::Error::Unauthenticated)
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn compute_tag_matches_encrypt_detached() -> Result<()> {
+        let key = [0x5b; 32];
+        let nonce = [0x0c; 12];
+
+        for (aad, len) in [
+            (&b""[..], 0),
+            (b"aad", 1),
+            (b"sixteen byte aad", 64),
+            (b"x", 300),
+        ] {
+            let plaintext = [0x99; 300];
+            let (ciphertext, tag) =
+                ChaCha20Poly1305::new(&key).encrypt_detached(&nonce, aad, &plaintext[..len])?;
+
+            assert_eq!(
+                ChaCha20Poly1305::compute_tag(&key, &nonce, aad, &ciphertext),
+                tag
+            );
+        }
+
+        // Encrypted separately with the keystream starting at the block 1
+        let mut ciphertext = *b"encrypted by someone else";
+        ChaCha20::new_with_cnt(&key, &nonce, 1).perform_in_place(&mut ciphertext)?;
+        let tag = ChaCha20Poly1305::compute_tag(&key, &nonce, b"aad", &ciphertext);
+
+        let plaintext =
+            ChaCha20Poly1305::new(&key).decrypt_detached(&nonce, b"aad", &ciphertext, &tag);
+        assert_eq!(plaintext.unwrap(), b"encrypted by someone else");
+
+        Ok(())
+    }
+
+    #[test]
+    fn rfc_8439_section_2_6_2_poly1305_key() {
+        let key: Key = core::array::from_fn(|i| 0x80 + i as u8);
+        let nonce: Nonce = [0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7];
+        let expected = [
+            0x8a, 0xd5, 0xa0, 0x8b, 0x90, 0x5f, 0x81, 0xcc, 0x81, 0x50, 0x40, 0x27, 0x4a, 0xb2,
+            0x94, 0x71, 0xa8, 0x33, 0xb6, 0x37, 0xe3, 0xfd, 0x0d, 0xa5, 0x08, 0xdb, 0xb8, 0xe2,
+            0xfd, 0xd1, 0xa6, 0x46,
+        ];
+
+        assert_eq!(ChaCha20Poly1305::poly1305_key(&key, &nonce), expected);
     }
 
     #[test]
@@ -333,7 +3288,7 @@ mod test {
             0x70, 0x72, 0x6f, 0x67, 0x72, 0x65, 0x73, 0x73, 0x2e, 0x2f, 0xe2, 0x80, 0x9d,
         ];
 
-        let cipher = ChaCha20Poly1305::new(&key, &nonce, &aad)?;
+        let cipher = AeadState::new(&key, &nonce, &aad)?;
         cipher.decrypt_oneshot_in_place(&mut ciphertext, &tag)?;
 
         assert_eq!(ciphertext, expected);
@@ -0,0 +1,86 @@
+use super::*;
+use aead::consts::{U0, U12, U16, U32};
+use aead::{AeadCore, AeadInPlace, KeyInit, KeySizeUser};
+
+impl KeySizeUser for ChaCha20Poly1305 {
+    type KeySize = U32;
+}
+
+impl KeyInit for ChaCha20Poly1305 {
+    fn new(key: &aead::Key<Self>) -> Self {
+        let mut key: Key = (*key).into();
+        let cipher = ChaCha20Poly1305::new(&key);
+        key.zeroize();
+        cipher
+    }
+}
+
+impl AeadCore for ChaCha20Poly1305 {
+    type NonceSize = U12;
+    type TagSize = U16;
+    type CiphertextOverhead = U0;
+}
+
+impl AeadInPlace for ChaCha20Poly1305 {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+    ) -> aead::Result<aead::Tag<Self>> {
+        ChaCha20Poly1305::encrypt_in_place_detached(self, &(*nonce).into(), associated_data, buffer)
+            .map(aead::Tag::<Self>::from)
+            .map_err(|_| aead::Error)
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tag: &aead::Tag<Self>,
+    ) -> aead::Result<()> {
+        ChaCha20Poly1305::decrypt_in_place_detached(
+            self,
+            &(*nonce).into(),
+            associated_data,
+            buffer,
+            &(*tag).into(),
+        )
+        .map_err(|_| aead::Error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn aead_in_place_round_trip() {
+        let key = [0x42; 32];
+        let nonce = aead::Nonce::<ChaCha20Poly1305>::from([0x24; 12]);
+        let aad = b"header";
+        let text = b"round trip through the aead traits";
+
+        let cipher = <ChaCha20Poly1305 as KeyInit>::new_from_slice(&key).unwrap();
+        let mut buffer = *text;
+
+        let tag =
+            AeadInPlace::encrypt_in_place_detached(&cipher, &nonce, aad, &mut buffer).unwrap();
+        assert_ne!(&buffer, text);
+
+        let mut bad_tag = tag;
+        bad_tag[0] ^= 0x01;
+        assert!(AeadInPlace::decrypt_in_place_detached(
+            &cipher,
+            &nonce,
+            aad,
+            &mut buffer,
+            &bad_tag
+        )
+        .is_err());
+
+        AeadInPlace::decrypt_in_place_detached(&cipher, &nonce, aad, &mut buffer, &tag).unwrap();
+        assert_eq!(&buffer, text);
+    }
+}
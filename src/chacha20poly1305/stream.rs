@@ -0,0 +1,314 @@
+//! Implementation of the STREAM construction for encrypting large data in chunks.
+//!
+//! Every chunk is sealed with the ChaCha20-Poly1305 AEAD under a nonce built from a prefix, a
+//! chunk counter and a flag marking the last chunk, so that reordering, duplicating or truncating
+//! the chunks is detected. The API is modeled on the `aead::stream` types of RustCrypto, and both
+//! of its nonce layouts are available:
+//!
+//! - `StreamBE32`: a 7 bytes prefix, a 32-bit big-endian counter and a final byte set to 1 for
+//!   the last chunk. This is `aead::stream::StreamBE32`, the layout of the original STREAM paper
+//!   also followed by age, which uses the whole 11 bytes as its counter.
+//! - `StreamLE31`: an 8 bytes prefix followed by a 31-bit little-endian counter, whose most
+//!   significant bit flags the last chunk. This is `aead::stream::StreamLE31`.
+
+use super::*;
+use core::marker::PhantomData;
+
+/// Represents the nonce prefix of the `StreamBE32` variant. It is an array of bytes with a size of 7.
+pub type NoncePrefix = [u8; 7];
+/// Represents the nonce prefix of the `StreamLE31` variant. It is an array of bytes with a size of 8.
+pub type NoncePrefixLE31 = [u8; 8];
+
+const LAST_BLOCK_FLAG: u8 = 0x01;
+
+/// Describes how a STREAM variant lays out the chunk counter and the last chunk flag in the nonce.
+pub trait StreamVariant {
+    /// The prefix completing the nonce, fixed for the whole stream
+    type NoncePrefix: Copy;
+    /// The largest chunk counter the nonce can hold
+    const COUNTER_MAX: u32;
+
+    /// Builds the nonce of the chunk at `counter`.
+    fn nonce(prefix: &Self::NoncePrefix, counter: u32, last: bool) -> Nonce;
+}
+
+/// STREAM with a 32-bit big-endian counter followed by a last chunk byte.
+pub struct StreamBE32;
+
+impl StreamVariant for StreamBE32 {
+    type NoncePrefix = NoncePrefix;
+    const COUNTER_MAX: u32 = u32::MAX;
+
+    #[inline]
+    fn nonce(prefix: &NoncePrefix, counter: u32, last: bool) -> Nonce {
+        let mut nonce: Nonce = Default::default();
+        nonce[..7].copy_from_slice(prefix);
+        nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+        nonce[11] = if last { LAST_BLOCK_FLAG } else { 0 };
+        nonce
+    }
+}
+
+/// STREAM with a 31-bit little-endian counter whose most significant bit flags the last chunk.
+pub struct StreamLE31;
+
+impl StreamVariant for StreamLE31 {
+    type NoncePrefix = NoncePrefixLE31;
+    const COUNTER_MAX: u32 = 0x7fff_ffff;
+
+    #[inline]
+    fn nonce(prefix: &NoncePrefixLE31, counter: u32, last: bool) -> Nonce {
+        let mut nonce: Nonce = Default::default();
+        nonce[..8].copy_from_slice(prefix);
+        nonce[8..].copy_from_slice(&(counter | (u32::from(last) << 31)).to_le_bytes());
+        nonce
+    }
+}
+
+/// Represents the encryptor of the STREAM construction, see `EncryptorBE32` and `EncryptorLE31`.
+pub struct Encryptor<S: StreamVariant> {
+    cipher: ChaCha20Poly1305,
+    nonce_prefix: S::NoncePrefix,
+    counter: u32,
+    allow_empty: bool,
+    variant: PhantomData<S>,
+}
+
+/// Represents the encryptor of the STREAM construction with a 32-bit big-endian chunk counter.
+pub type EncryptorBE32 = Encryptor<StreamBE32>;
+/// Represents the encryptor of the STREAM construction with a 31-bit little-endian chunk counter.
+pub type EncryptorLE31 = Encryptor<StreamLE31>;
+
+impl<S: StreamVariant> Encryptor<S> {
+    /// Creates a new Encryptor instance with the provided key and nonce prefix.
+    pub fn new(key: &Key, nonce_prefix: &S::NoncePrefix) -> Self {
+        Self::from_aead(ChaCha20Poly1305::new(key), nonce_prefix)
+    }
+
+    /// Creates a new Encryptor instance from an already keyed AEAD cipher.
+    pub fn from_aead(cipher: ChaCha20Poly1305, nonce_prefix: &S::NoncePrefix) -> Self {
+        Self {
+            cipher,
+            nonce_prefix: *nonce_prefix,
+            counter: 0,
+            allow_empty: false,
+            variant: PhantomData,
+        }
+    }
+
+    #[inline]
+    /// Allows sealing a stream without any data, which `encrypt_last` otherwise rejects with
+    /// `Error::EmptyStream` as it usually indicates a bug.
+    pub fn allow_empty_stream(mut self) -> Self {
+        self.allow_empty = true;
+        self
+    }
+
+    /// Encrypts the next chunk of the stream and returns the ciphertext with the tag appended.
+    /// Returns `Error::DataTooLong` when the chunk counter would overflow.
+    pub fn encrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        if self.counter >= S::COUNTER_MAX {
+            return Err(error::Error::DataTooLong);
+        }
+
+        let nonce = S::nonce(&self.nonce_prefix, self.counter, false);
+        let res = self.cipher.encrypt(&nonce, &[], chunk)?;
+        self.counter += 1;
+        Ok(res)
+    }
+
+    /// Encrypts the last chunk of the stream and returns the ciphertext with the tag appended.
+    /// Returns `Error::EmptyStream` when the whole stream would be empty, unless allowed with
+    /// `allow_empty_stream`.
+    pub fn encrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>> {
+        if self.counter == 0 && chunk.is_empty() && !self.allow_empty {
+            return Err(error::Error::EmptyStream);
+        }
+
+        let nonce = S::nonce(&self.nonce_prefix, self.counter, true);
+        self.cipher.encrypt(&nonce, &[], chunk)
+    }
+}
+
+/// Represents the decryptor of the STREAM construction, see `DecryptorBE32` and `DecryptorLE31`.
+pub struct Decryptor<S: StreamVariant> {
+    cipher: ChaCha20Poly1305,
+    nonce_prefix: S::NoncePrefix,
+    counter: u32,
+    variant: PhantomData<S>,
+}
+
+/// Represents the decryptor of the STREAM construction with a 32-bit big-endian chunk counter.
+pub type DecryptorBE32 = Decryptor<StreamBE32>;
+/// Represents the decryptor of the STREAM construction with a 31-bit little-endian chunk counter.
+pub type DecryptorLE31 = Decryptor<StreamLE31>;
+
+impl<S: StreamVariant> Decryptor<S> {
+    /// Creates a new Decryptor instance with the provided key and nonce prefix.
+    pub fn new(key: &Key, nonce_prefix: &S::NoncePrefix) -> Self {
+        Self::from_aead(ChaCha20Poly1305::new(key), nonce_prefix)
+    }
+
+    /// Creates a new Decryptor instance from an already keyed AEAD cipher.
+    pub fn from_aead(cipher: ChaCha20Poly1305, nonce_prefix: &S::NoncePrefix) -> Self {
+        Self {
+            cipher,
+            nonce_prefix: *nonce_prefix,
+            counter: 0,
+            variant: PhantomData,
+        }
+    }
+
+    /// Decrypts the next chunk of the stream (ciphertext followed by the tag).
+    /// Returns `Error::DataTooLong` when the chunk counter would overflow.
+    pub fn decrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        if self.counter >= S::COUNTER_MAX {
+            return Err(error::Error::DataTooLong);
+        }
+
+        let nonce = S::nonce(&self.nonce_prefix, self.counter, false);
+        let res = self.cipher.decrypt(&nonce, &[], chunk)?;
+        self.counter += 1;
+        Ok(res)
+    }
+
+    /// Decrypts the last chunk of the stream (ciphertext followed by the tag).
+    pub fn decrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>> {
+        let nonce = S::nonce(&self.nonce_prefix, self.counter, true);
+        Ok(self.cipher.decrypt(&nonce, &[], chunk)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: Key = [0x42; 32];
+    const PREFIX: NoncePrefix = [0x24; 7];
+
+    #[test]
+    fn be32_round_trip() -> Result<()> {
+        let mut encryptor = EncryptorBE32::new(&KEY, &PREFIX);
+        let first = encryptor.encrypt_next(b"first chunk")?;
+        let second = encryptor.encrypt_next(b"second chunk")?;
+        let last = encryptor.encrypt_last(b"last chunk")?;
+
+        let mut decryptor = DecryptorBE32::new(&KEY, &PREFIX);
+        assert_eq!(decryptor.decrypt_next(&first)?, b"first chunk");
+        assert_eq!(decryptor.decrypt_next(&second)?, b"second chunk");
+        assert_eq!(decryptor.decrypt_last(&last)?, b"last chunk");
+
+        Ok(())
+    }
+
+    #[test]
+    fn be32_detects_truncation_and_reordering() -> Result<()> {
+        let mut encryptor = EncryptorBE32::new(&KEY, &PREFIX);
+        let first = encryptor.encrypt_next(b"first chunk")?;
+        let second = encryptor.encrypt_next(b"second chunk")?;
+        let last = encryptor.encrypt_last(b"last chunk")?;
+
+        // The stream is truncated after the second chunk
+        let mut decryptor = DecryptorBE32::new(&KEY, &PREFIX);
+        decryptor.decrypt_next(&first)?;
+        assert_eq!(
+            decryptor.decrypt_last(&second).unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        // The last chunk is not accepted in the middle of the stream
+        let mut decryptor = DecryptorBE32::new(&KEY, &PREFIX);
+        decryptor.decrypt_next(&first)?;
+        decryptor.decrypt_next(&second)?;
+        assert_eq!(
+            decryptor.decrypt_next(&last).unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        // Swapped chunks
+        let mut decryptor = DecryptorBE32::new(&KEY, &PREFIX);
+        assert_eq!(
+            decryptor.decrypt_next(&second).unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn be32_counter_overflow() {
+        let mut encryptor = EncryptorBE32::new(&KEY, &PREFIX);
+        encryptor.counter = u32::MAX;
+
+        assert_eq!(
+            encryptor.encrypt_next(b"chunk").unwrap_err(),
+            error::Error::DataTooLong
+        );
+        assert!(encryptor.encrypt_last(b"chunk").is_ok());
+    }
+
+    #[test]
+    fn be32_empty_stream() -> Result<()> {
+        let encryptor = EncryptorBE32::new(&KEY, &PREFIX);
+        assert_eq!(
+            encryptor.encrypt_last(&[]).unwrap_err(),
+            error::Error::EmptyStream
+        );
+
+        // An empty last chunk is fine after some data
+        let mut encryptor = EncryptorBE32::new(&KEY, &PREFIX);
+        encryptor.encrypt_next(b"data")?;
+        encryptor.encrypt_last(&[])?;
+
+        let last = EncryptorBE32::new(&KEY, &PREFIX)
+            .allow_empty_stream()
+            .encrypt_last(&[])?;
+        assert_eq!(DecryptorBE32::new(&KEY, &PREFIX).decrypt_last(&last)?, b"");
+
+        Ok(())
+    }
+
+    #[test]
+    fn le31_round_trip() -> Result<()> {
+        const PREFIX_LE31: NoncePrefixLE31 = [0x31; 8];
+
+        let mut encryptor = EncryptorLE31::new(&KEY, &PREFIX_LE31);
+        let first = encryptor.encrypt_next(b"first chunk")?;
+        let second = encryptor.encrypt_next(b"second chunk")?;
+        let last = encryptor.encrypt_last(b"last chunk")?;
+
+        let mut decryptor = DecryptorLE31::new(&KEY, &PREFIX_LE31);
+        assert_eq!(decryptor.decrypt_next(&first)?, b"first chunk");
+        assert_eq!(decryptor.decrypt_next(&second)?, b"second chunk");
+        assert_eq!(decryptor.decrypt_last(&last)?, b"last chunk");
+
+        // The chunks are sealed under the LE31 nonces
+        let cipher = ChaCha20Poly1305::new(&KEY);
+        let mut nonce = [0x31; 12];
+        nonce[8..].copy_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+        assert_eq!(cipher.decrypt(&nonce, &[], &second)?, b"second chunk");
+        nonce[8..].copy_from_slice(&[0x02, 0x00, 0x00, 0x80]);
+        assert_eq!(cipher.decrypt(&nonce, &[], &last)?, b"last chunk");
+
+        // Chunks of one variant are rejected by the other
+        let mut decryptor = DecryptorBE32::new(&KEY, &PREFIX);
+        assert_eq!(
+            decryptor.decrypt_next(&first).unwrap_err(),
+            error::Error::Unauthenticated
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn le31_counter_overflow() {
+        let mut encryptor = EncryptorLE31::new(&KEY, &[0x31; 8]);
+        encryptor.counter = 0x7fff_ffff;
+
+        assert_eq!(
+            encryptor.encrypt_next(b"chunk").unwrap_err(),
+            error::Error::DataTooLong
+        );
+        assert!(encryptor.encrypt_last(b"chunk").is_ok());
+    }
+}
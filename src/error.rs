@@ -1,11 +1,18 @@
 use core::fmt;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Error {
     DataTooLong,
     AadTooLong,
     Unauthenticated,
     OutOfMemory,
+    InternalState,
+    AadAfterData,
+    EmptyStream,
+    CipherCleared,
+    MalformedFrame,
+    InvalidLength,
+    RandomnessUnavailable,
 }
 
 impl fmt::Display for Error {
@@ -15,6 +22,86 @@ impl fmt::Display for Error {
             Self::AadTooLong => write!(f, "Additional Data is too long"),
             Self::Unauthenticated => write!(f, "Unauthenticated"),
             Self::OutOfMemory => write!(f, "Out Of Memory"),
+            Self::InternalState => write!(f, "Internal state is corrupted"),
+            Self::AadAfterData => write!(f, "Additional Data supplied after the data"),
+            Self::EmptyStream => write!(f, "Stream finalized without any data"),
+            Self::CipherCleared => write!(f, "Cipher used after being cleared"),
+            Self::MalformedFrame => write!(f, "Frame is malformed"),
+            Self::InvalidLength => write!(f, "Input has an invalid length"),
+            Self::RandomnessUnavailable => write!(f, "System randomness is unavailable"),
         }
     }
 }
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+/// The error of the `ChaCha20Poly1305` decryption methods, which can only fail because the
+/// message is not authentic or because the plaintext could not be allocated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecryptError {
+    Unauthenticated,
+    OutOfMemory,
+}
+
+impl From<DecryptError> for Error {
+    fn from(err: DecryptError) -> Self {
+        match err {
+            DecryptError::Unauthenticated => Self::Unauthenticated,
+            DecryptError::OutOfMemory => Self::OutOfMemory,
+        }
+    }
+}
+
+impl Error {
+    /// Narrows the error of a decryption step. A message too long for the keystream or with an
+    /// oversized AAD cannot have been sealed by this cipher, so it is reported as unauthenticated.
+    #[inline]
+    pub(crate) fn into_decrypt_error(self) -> DecryptError {
+        match self {
+            Self::OutOfMemory => DecryptError::OutOfMemory,
+            _ => DecryptError::Unauthenticated,
+        }
+    }
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Error::from(*self).fmt(f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecryptError {}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use std::boxed::Box;
+    use std::string::ToString;
+
+    #[test]
+    fn boxed_std_error() {
+        let err: Box<dyn std::error::Error> = Box::new(Error::Unauthenticated);
+        assert!(err.source().is_none());
+        assert_eq!(err.to_string(), "Unauthenticated");
+    }
+
+    #[test]
+    fn decrypt_error_converts() {
+        assert_eq!(
+            Error::from(DecryptError::Unauthenticated),
+            Error::Unauthenticated
+        );
+        assert_eq!(Error::from(DecryptError::OutOfMemory), Error::OutOfMemory);
+        assert_eq!(
+            Error::DataTooLong.into_decrypt_error(),
+            DecryptError::Unauthenticated
+        );
+        assert_eq!(DecryptError::OutOfMemory.to_string(), "Out Of Memory");
+    }
+}
@@ -0,0 +1,333 @@
+//! C-compatible interface of the ChaCha20-Poly1305 AEAD cipher.
+//!
+//! Every function returns `SCO_OK` on success or one of the negative `SCO_ERR_*` codes on failure.
+//! The sealed message layout is the ciphertext followed by the 16 bytes tag.
+
+use crate::error::Error;
+use crate::{ChaCha20Poly1305, Key, Nonce, Tag};
+use core::slice;
+use zeroize::Zeroize as _;
+
+const TAG_SIZE: usize = core::mem::size_of::<Tag>();
+
+/// The operation succeeded.
+pub const SCO_OK: i32 = 0;
+/// Maps to `Error::DataTooLong`.
+pub const SCO_ERR_DATA_TOO_LONG: i32 = -1;
+/// Maps to `Error::AadTooLong`.
+pub const SCO_ERR_AAD_TOO_LONG: i32 = -2;
+/// Maps to `Error::Unauthenticated`.
+pub const SCO_ERR_UNAUTHENTICATED: i32 = -3;
+/// Maps to `Error::OutOfMemory`.
+pub const SCO_ERR_OUT_OF_MEMORY: i32 = -4;
+/// Maps to `Error::InternalState`.
+pub const SCO_ERR_INTERNAL_STATE: i32 = -5;
+/// A required pointer was null.
+pub const SCO_ERR_NULL_POINTER: i32 = -6;
+/// The output buffer is too small, the required size is written to `out_len_ptr`.
+pub const SCO_ERR_BUFFER_TOO_SMALL: i32 = -7;
+/// Maps to `Error::AadAfterData`.
+pub const SCO_ERR_AAD_AFTER_DATA: i32 = -8;
+/// Maps to `Error::EmptyStream`.
+pub const SCO_ERR_EMPTY_STREAM: i32 = -9;
+/// Maps to `Error::CipherCleared`.
+pub const SCO_ERR_CIPHER_CLEARED: i32 = -10;
+/// Maps to `Error::MalformedFrame`.
+pub const SCO_ERR_MALFORMED_FRAME: i32 = -11;
+/// Maps to `Error::InvalidLength`.
+pub const SCO_ERR_INVALID_LENGTH: i32 = -12;
+/// Maps to `Error::RandomnessUnavailable`.
+pub const SCO_ERR_RANDOMNESS_UNAVAILABLE: i32 = -13;
+
+fn error_code(err: Error) -> i32 {
+    match err {
+        Error::DataTooLong => SCO_ERR_DATA_TOO_LONG,
+        Error::AadTooLong => SCO_ERR_AAD_TOO_LONG,
+        Error::Unauthenticated => SCO_ERR_UNAUTHENTICATED,
+        Error::OutOfMemory => SCO_ERR_OUT_OF_MEMORY,
+        Error::InternalState => SCO_ERR_INTERNAL_STATE,
+        Error::AadAfterData => SCO_ERR_AAD_AFTER_DATA,
+        Error::EmptyStream => SCO_ERR_EMPTY_STREAM,
+        Error::CipherCleared => SCO_ERR_CIPHER_CLEARED,
+        Error::MalformedFrame => SCO_ERR_MALFORMED_FRAME,
+        Error::InvalidLength => SCO_ERR_INVALID_LENGTH,
+        Error::RandomnessUnavailable => SCO_ERR_RANDOMNESS_UNAVAILABLE,
+    }
+}
+
+/// Builds a slice from a C buffer, a null pointer is only accepted for an empty buffer.
+unsafe fn slice_from<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(slice::from_raw_parts(ptr, len)),
+    }
+}
+
+/// Encrypts `pt_len` bytes at `pt_ptr` and writes the ciphertext followed by the tag to `out_ptr`.
+///
+/// `out_cap` must be at least `pt_len + 16`, otherwise `SCO_ERR_BUFFER_TOO_SMALL` is returned and
+/// the required size is written to `out_len_ptr`. On success the number of written bytes is
+/// written to `out_len_ptr`.
+///
+/// # Safety
+///
+/// - `key_ptr` must point to 32 readable bytes and `nonce_ptr` to 12 readable bytes.
+/// - `aad_ptr` and `pt_ptr` must point to `aad_len` and `pt_len` readable bytes, they may be null
+///   when their length is 0.
+/// - `out_ptr` must point to `out_cap` writable bytes not overlapping any of the inputs.
+/// - `out_len_ptr` must point to a writable `usize`.
+#[no_mangle]
+pub unsafe extern "C" fn sco_aead_encrypt(
+    key_ptr: *const u8,
+    nonce_ptr: *const u8,
+    aad_ptr: *const u8,
+    aad_len: usize,
+    pt_ptr: *const u8,
+    pt_len: usize,
+    out_ptr: *mut u8,
+    out_cap: usize,
+    out_len_ptr: *mut usize,
+) -> i32 {
+    if key_ptr.is_null() || nonce_ptr.is_null() || out_len_ptr.is_null() {
+        return SCO_ERR_NULL_POINTER;
+    }
+
+    let (Some(aad), Some(plaintext)) = (slice_from(aad_ptr, aad_len), slice_from(pt_ptr, pt_len))
+    else {
+        return SCO_ERR_NULL_POINTER;
+    };
+
+    let Some(required) = pt_len.checked_add(TAG_SIZE) else {
+        return SCO_ERR_DATA_TOO_LONG;
+    };
+
+    if out_cap < required {
+        *out_len_ptr = required;
+        return SCO_ERR_BUFFER_TOO_SMALL;
+    }
+
+    if out_ptr.is_null() {
+        return SCO_ERR_NULL_POINTER;
+    }
+
+    let key = &*(key_ptr as *const Key);
+    let nonce = &*(nonce_ptr as *const Nonce);
+    let out = slice::from_raw_parts_mut(out_ptr, required);
+    let (ciphertext, tag_out) = out.split_at_mut(pt_len);
+
+    ciphertext.copy_from_slice(plaintext);
+
+    match ChaCha20Poly1305::new(key).encrypt_in_place_detached(nonce, aad, ciphertext) {
+        Ok(tag) => {
+            tag_out.copy_from_slice(&tag);
+            *out_len_ptr = required;
+            SCO_OK
+        }
+        Err(err) => {
+            ciphertext.zeroize();
+            error_code(err)
+        }
+    }
+}
+
+/// Decrypts `ct_len` bytes at `ct_ptr` (the ciphertext followed by the tag) and writes the
+/// plaintext to `out_ptr`. The ciphertext is decrypted in `out_ptr` after the tag is verified, so
+/// when the tag is invalid the first `ct_len - 16` bytes of `out_ptr` are zeroed.
+///
+/// `out_cap` must be at least `ct_len - 16`, otherwise `SCO_ERR_BUFFER_TOO_SMALL` is returned and
+/// the required size is written to `out_len_ptr`. An input shorter than the tag is reported as
+/// `SCO_ERR_UNAUTHENTICATED`. On success the number of written bytes is written to `out_len_ptr`.
+///
+/// # Safety
+///
+/// - `key_ptr` must point to 32 readable bytes and `nonce_ptr` to 12 readable bytes.
+/// - `aad_ptr` and `ct_ptr` must point to `aad_len` and `ct_len` readable bytes, they may be null
+///   when their length is 0.
+/// - `out_ptr` must point to `out_cap` writable bytes not overlapping any of the inputs.
+/// - `out_len_ptr` must point to a writable `usize`.
+#[no_mangle]
+pub unsafe extern "C" fn sco_aead_decrypt(
+    key_ptr: *const u8,
+    nonce_ptr: *const u8,
+    aad_ptr: *const u8,
+    aad_len: usize,
+    ct_ptr: *const u8,
+    ct_len: usize,
+    out_ptr: *mut u8,
+    out_cap: usize,
+    out_len_ptr: *mut usize,
+) -> i32 {
+    if key_ptr.is_null() || nonce_ptr.is_null() || out_len_ptr.is_null() {
+        return SCO_ERR_NULL_POINTER;
+    }
+
+    let (Some(aad), Some(ct_with_tag)) = (slice_from(aad_ptr, aad_len), slice_from(ct_ptr, ct_len))
+    else {
+        return SCO_ERR_NULL_POINTER;
+    };
+
+    let Some(required) = ct_len.checked_sub(TAG_SIZE) else {
+        return SCO_ERR_UNAUTHENTICATED;
+    };
+
+    if out_cap < required {
+        *out_len_ptr = required;
+        return SCO_ERR_BUFFER_TOO_SMALL;
+    }
+
+    if out_ptr.is_null() && required != 0 {
+        return SCO_ERR_NULL_POINTER;
+    }
+
+    let key = &*(key_ptr as *const Key);
+    let nonce = &*(nonce_ptr as *const Nonce);
+    let (ciphertext, tag) = ct_with_tag.split_at(required);
+    // Unwrap here is safe because the tag slice is exactly TAG_SIZE bytes long
+    let tag: &Tag = tag.try_into().unwrap();
+
+    let mut empty = [];
+    let out = if required == 0 {
+        &mut empty[..]
+    } else {
+        slice::from_raw_parts_mut(out_ptr, required)
+    };
+
+    out.copy_from_slice(ciphertext);
+
+    match ChaCha20Poly1305::new(key).decrypt_in_place_detached(nonce, aad, out, tag) {
+        Ok(()) => {
+            *out_len_ptr = required;
+            SCO_OK
+        }
+        Err(err) => {
+            out.zeroize();
+            error_code(err.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::ptr;
+
+    #[test]
+    fn ffi_round_trip() {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+        let aad = b"header";
+        let text = b"message crossing the C boundary";
+
+        let mut sealed = [0u8; 31 + TAG_SIZE];
+        let mut sealed_len = 0;
+
+        let res = unsafe {
+            sco_aead_encrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                text.as_ptr(),
+                text.len(),
+                sealed.as_mut_ptr(),
+                sealed.len(),
+                &mut sealed_len,
+            )
+        };
+        assert_eq!(res, SCO_OK);
+        assert_eq!(sealed_len, text.len() + TAG_SIZE);
+
+        let mut opened = [0u8; 31];
+        let mut opened_len = 0;
+
+        let res = unsafe {
+            sco_aead_decrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                sealed.as_ptr(),
+                sealed_len,
+                opened.as_mut_ptr(),
+                opened.len(),
+                &mut opened_len,
+            )
+        };
+        assert_eq!(res, SCO_OK);
+        assert_eq!(&opened[..opened_len], text);
+
+        sealed[0] ^= 0x01;
+        let res = unsafe {
+            sco_aead_decrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                sealed.as_ptr(),
+                sealed_len,
+                opened.as_mut_ptr(),
+                opened.len(),
+                &mut opened_len,
+            )
+        };
+        assert_eq!(res, SCO_ERR_UNAUTHENTICATED);
+        assert_eq!(opened, [0; 31]);
+    }
+
+    #[test]
+    fn ffi_buffer_contracts() {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+        let text = b"short";
+
+        let mut out = [0u8; 8];
+        let mut out_len = 0;
+
+        let res = unsafe {
+            sco_aead_encrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                ptr::null(),
+                0,
+                text.as_ptr(),
+                text.len(),
+                out.as_mut_ptr(),
+                out.len(),
+                &mut out_len,
+            )
+        };
+        assert_eq!(res, SCO_ERR_BUFFER_TOO_SMALL);
+        assert_eq!(out_len, text.len() + TAG_SIZE);
+
+        let res = unsafe {
+            sco_aead_decrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                ptr::null(),
+                0,
+                out.as_ptr(),
+                5,
+                out.as_mut_ptr(),
+                out.len(),
+                &mut out_len,
+            )
+        };
+        assert_eq!(res, SCO_ERR_UNAUTHENTICATED);
+
+        let res = unsafe {
+            sco_aead_encrypt(
+                ptr::null(),
+                nonce.as_ptr(),
+                ptr::null(),
+                0,
+                text.as_ptr(),
+                text.len(),
+                out.as_mut_ptr(),
+                out.len(),
+                &mut out_len,
+            )
+        };
+        assert_eq!(res, SCO_ERR_NULL_POINTER);
+    }
+}
@@ -2,15 +2,33 @@
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+// The variable-time tag comparison must never end up in a release build by accident, such builds
+// have to opt in explicitly with `RUSTFLAGS="--cfg sco_insecure_benchmarks"`.
+#[cfg(all(
+    feature = "variable-time-verify",
+    not(debug_assertions),
+    not(sco_insecure_benchmarks)
+))]
+compile_error!(
+    "the `variable-time-verify` feature is insecure, release builds using it must also be \
+     compiled with `--cfg sco_insecure_benchmarks`"
+);
 
 pub type Result<T> = core::result::Result<T, error::Error>;
 
 pub mod chacha20;
 pub mod chacha20poly1305;
 pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod poly1305;
+pub mod secret;
 
 pub use chacha20::ChaCha20;
+pub use chacha20::ChaCha20Legacy;
 pub use chacha20::Key;
 pub use chacha20::Nonce;
 
@@ -18,7 +36,13 @@ pub use poly1305::Key as Poly1305Key;
 pub use poly1305::Poly1305;
 pub use poly1305::Tag;
 
+pub use chacha20poly1305::AeadState;
 pub use chacha20poly1305::ChaCha20Poly1305;
+pub use chacha20poly1305::StreamEncryptor;
+#[cfg(feature = "alloc")]
+pub use chacha20poly1305::StreamingOpener;
+#[cfg(feature = "alloc")]
+pub use chacha20poly1305::StreamingSealer;
 
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
@@ -29,7 +53,17 @@ pub(crate) fn try_to_vec(data: &[u8]) -> Result<Vec<u8>> {
 
     res.try_reserve(data.len())
         .map_err(|_| error::Error::OutOfMemory)?;
-    res.copy_from_slice(data);
+    res.extend_from_slice(data);
 
     Ok(res)
 }
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn try_to_vec_copies_the_data() {
+        assert_eq!(super::try_to_vec(b"").unwrap(), b"");
+        assert_eq!(super::try_to_vec(b"some data").unwrap(), b"some data");
+    }
+}
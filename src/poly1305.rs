@@ -2,6 +2,7 @@
 
 mod cross_arch;
 
+use crate::error::Error;
 use core::hint::black_box;
 use cross_arch::Poly1305Inner;
 use zeroize::Zeroize;
@@ -10,6 +11,9 @@ const KEY_SIZE: usize = 256;
 const TAG_SIZE: usize = 128;
 const BLOCK_SIZE: usize = 16;
 
+/// Size in bytes of the state exported by `Poly1305::export_state`
+pub const EXPORTED_STATE_SIZE: usize = 3 * 8 + BLOCK_SIZE + 1;
+
 /// Represents the Poly1305 key. It is an array of bytes with a size of 32, or 256 bits
 pub type Key = [u8; KEY_SIZE / 8];
 /// Represents the Poly1305 authentication tag. It is an array of bytes with a size of 16, or 128
@@ -18,6 +22,17 @@ pub type Tag = [u8; TAG_SIZE / 8];
 
 type Block = [u8; BLOCK_SIZE];
 
+#[derive(Clone)]
+/// Represents the partial accumulator of Poly1305 over a segment of a message, see
+/// `Poly1305::merge_segments`.
+pub struct PartialAcc([u64; 3]);
+
+impl Drop for PartialAcc {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
 #[derive(Clone)]
 /// Represents the Poly1305 state.
 pub struct Poly1305 {
@@ -27,15 +42,47 @@ pub struct Poly1305 {
 }
 
 impl Poly1305 {
+    /// Size of the key in bytes
+    pub const KEY_SIZE: usize = core::mem::size_of::<Key>();
+    /// Size of the tag in bytes
+    pub const TAG_SIZE: usize = core::mem::size_of::<Tag>();
+    /// Size of a message block in bytes
+    pub const BLOCK_SIZE: usize = BLOCK_SIZE;
+
     /// Creates a new Poly1305 instance with the provided key.
-    pub fn new(key: &Key) -> Self {
+    pub const fn new(key: &Key) -> Self {
         Self {
             inner: Poly1305Inner::new(key),
-            buffer: Default::default(),
+            buffer: [0; BLOCK_SIZE],
+            leftover: 0,
+        }
+    }
+
+    /// Creates a new Poly1305 instance from the `r` and `s` halves of the key given separately.
+    ///
+    /// `r` must already be clamped as described in RFC 8439, it is used as is.
+    pub const fn from_r_s(r: &[u8; 16], s: &[u8; 16]) -> Self {
+        Self {
+            inner: Poly1305Inner::from_r_s(r, s),
+            buffer: [0; BLOCK_SIZE],
             leftover: 0,
         }
     }
 
+    /// Computes the Poly1305 tag of the given data in one call.
+    pub fn compute(key: &Key, data: &[u8]) -> Tag {
+        let mut mac = Self::new(key);
+        mac.update(data);
+        mac.finalize()
+    }
+
+    /// Verifies in one call if the provided tag matches the Poly1305 tag of the given data.
+    pub fn verify_oneshot(key: &Key, data: &[u8], tag: &Tag) -> bool {
+        let mut mac = Self::new(key);
+        mac.update(data);
+        mac.verify(tag)
+    }
+
     /// Updates the Poly1305 state with the given data.
     pub fn update(&mut self, data: &[u8]) {
         let mut start_idx = 0;
@@ -47,7 +94,8 @@ impl Poly1305 {
                 leftover_fill_size = data.len();
             }
 
-            self.buffer[self.leftover..].copy_from_slice(&data[..leftover_fill_size]);
+            self.buffer[self.leftover..self.leftover + leftover_fill_size]
+                .copy_from_slice(&data[..leftover_fill_size]);
             self.leftover += leftover_fill_size;
 
             start_idx += leftover_fill_size;
@@ -72,6 +120,96 @@ impl Poly1305 {
         }
     }
 
+    #[inline]
+    /// Updates the Poly1305 state with the given data like `update` and returns the state, so
+    /// calls can be chained.
+    pub fn chain_update(mut self, data: &[u8]) -> Self {
+        self.update(data);
+        self
+    }
+
+    /// Updates the Poly1305 state with each of the given buffers in order, as if they were
+    /// concatenated. Meant to be fed from `iovec`-like buffer descriptors of an FFI layer.
+    pub fn update_iovec(&mut self, iov: &[&[u8]]) {
+        for data in iov {
+            self.update(data);
+        }
+    }
+
+    /// Updates the Poly1305 state with several byte streams interleaved into a single message.
+    ///
+    /// The streams are visited in order, taking the next `round_robin_bytes` bytes of each one, or
+    /// its remaining bytes if fewer, before moving to the next stream. After the last stream the
+    /// next round starts again from the first one, until a whole round yields no byte. With two
+    /// streams `a` and `b` and 2 bytes per round, the message is `a0 a1 b0 b1 a2 a3 b2 b3 ...`,
+    /// and once `a` is exhausted the rest of `b` follows 2 bytes at a time.
+    ///
+    /// Exhausted streams are polled again in every later round, so iterators that may yield more
+    /// bytes after returning `None` should be fused.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `round_robin_bytes` is 0.
+    pub fn update_interleaved(
+        &mut self,
+        streams: &mut [impl Iterator<Item = u8>],
+        round_robin_bytes: usize,
+    ) {
+        assert!(round_robin_bytes != 0, "round_robin_bytes must not be 0");
+
+        let mut chunk = [0; BLOCK_SIZE];
+
+        loop {
+            let mut exhausted = true;
+
+            for stream in streams.iter_mut() {
+                let mut remaining = round_robin_bytes;
+
+                while remaining != 0 {
+                    let mut len = 0;
+                    for byte in stream.by_ref().take(remaining.min(BLOCK_SIZE)) {
+                        chunk[len] = byte;
+                        len += 1;
+                    }
+
+                    if len == 0 {
+                        break;
+                    }
+
+                    self.update(&chunk[..len]);
+                    remaining -= len;
+                    exhausted = false;
+                }
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        chunk.zeroize();
+    }
+
+    #[cfg(feature = "std")]
+    /// Updates the Poly1305 state with each of the `IoSlice`s in order, as if they were
+    /// concatenated, like `update_iovec` for the buffers of a vectored write.
+    pub fn update_vectored(&mut self, slices: &[std::io::IoSlice<'_>]) {
+        for data in slices {
+            self.update(data);
+        }
+    }
+
+    /// Updates the Poly1305 state with the given data like `update`, but validates the internal
+    /// invariants first and returns `Error::InternalState` instead of panicking if they are broken.
+    pub fn try_update(&mut self, data: &[u8]) -> crate::Result<()> {
+        if self.leftover >= BLOCK_SIZE {
+            return Err(Error::InternalState);
+        }
+
+        self.update(data);
+        Ok(())
+    }
+
     #[inline]
     fn leftover_pad16(&mut self) {
         if self.leftover != BLOCK_SIZE {
@@ -90,43 +228,248 @@ impl Poly1305 {
         self.leftover = 0;
     }
 
-    /// Finalizes the Poly1305 state and returns the authentication tag.
-    pub fn finalize(mut self) -> Tag {
+    /// Reinitializes the Poly1305 state with a new key so the same instance can be reused.
+    /// The previous key schedule and any buffered data are fully overwritten.
+    pub fn reset(&mut self, key: &Key) {
+        self.inner = Poly1305Inner::new(key);
+        self.buffer.zeroize();
+        self.leftover = 0;
+    }
+
+    #[inline]
+    fn append_final_block(&mut self) {
         if self.leftover != 0 {
             self.buffer[self.leftover] = 0x01;
             self.leftover += 1;
             self.leftover_pad16();
             self.inner.append_block(&self.buffer, true);
         }
+    }
 
+    /// Finalizes the Poly1305 state and returns the authentication tag.
+    pub fn finalize(mut self) -> Tag {
+        self.append_final_block();
         self.inner.finish()
     }
 
+    /// Finalizes the Poly1305 state and returns the authentication tag, then reinitializes the
+    /// state with a new key like `reset` so the same instance can authenticate the next message.
+    pub fn finalize_reset(&mut self, key: &Key) -> Tag {
+        self.append_final_block();
+        let inner = core::mem::replace(&mut self.inner, Poly1305Inner::new(key));
+        self.buffer.zeroize();
+        self.leftover = 0;
+        inner.finish()
+    }
+
+    /// Exports the progress of the computation, to be resumed later with `import_state` and the
+    /// same key. The key itself is not part of the exported state.
+    ///
+    /// The layout is the three accumulator limbs as `u64` LE, the buffered bytes and the number of
+    /// bytes buffered. The exported state reveals information about the authenticated data and
+    /// must be kept as secret as the key.
+    pub fn export_state(&self) -> [u8; EXPORTED_STATE_SIZE] {
+        let mut state = [0; EXPORTED_STATE_SIZE];
+
+        for (out, limb) in state.chunks_exact_mut(8).zip(self.inner.accumulator()) {
+            out.copy_from_slice(&limb.to_le_bytes());
+        }
+
+        state[24..40].copy_from_slice(&self.buffer);
+        // Cast here is lossless because the leftover is always below BLOCK_SIZE
+        state[40] = self.leftover as u8;
+        state
+    }
+
+    /// Resumes a computation exported by `export_state` under the same key.
+    ///
+    /// Returns `Error::InternalState` if the state could not have been exported by
+    /// `export_state`.
+    pub fn import_state(key: &Key, state: &[u8; EXPORTED_STATE_SIZE]) -> crate::Result<Self> {
+        let limb = |i: usize| u64::from_le_bytes(state[i * 8..i * 8 + 8].try_into().unwrap());
+        let h = [limb(0), limb(1), limb(2)];
+        let leftover = usize::from(state[40]);
+
+        if h[0] >> 44 != 0 || h[1] >> 45 != 0 || h[2] >> 42 != 0 || leftover >= BLOCK_SIZE {
+            return Err(Error::InternalState);
+        }
+
+        let mut mac = Self::new(key);
+        mac.inner.set_accumulator(h);
+        mac.buffer.copy_from_slice(&state[24..40]);
+        mac.leftover = leftover;
+        Ok(mac)
+    }
+
+    /// Resumes a computation like `import_state` from a slice, returning `Error::InvalidLength`
+    /// if it is not exactly `EXPORTED_STATE_SIZE` bytes long.
+    pub fn import_state_slice(key: &Key, bytes: &[u8]) -> crate::Result<Self> {
+        let state = bytes.try_into().map_err(|_| Error::InvalidLength)?;
+        Self::import_state(key, state)
+    }
+
+    /// Finalizes the Poly1305 state of a message segment into its partial accumulator, to be
+    /// combined later with `merge_segments`. The `s` half of the key is not applied.
+    pub fn into_partial(mut self) -> PartialAcc {
+        self.append_final_block();
+        PartialAcc(self.inner.accumulator())
+    }
+
+    /// Combines the partial accumulators of consecutive segments of a message into the tag of the
+    /// whole message, allowing the segments to be authenticated in parallel.
+    ///
+    /// Each segment is given with the number of 16 bytes blocks it covers, rounded up. Every
+    /// segment except the last one must be a multiple of 16 bytes long, and all of them must be
+    /// computed with the same `key`.
+    pub fn merge_segments(key: &Key, segment_tags: &[(PartialAcc, u64)]) -> Tag {
+        let mut inner = Poly1305Inner::new(key);
+        inner.merge(segment_tags.iter().map(|(acc, blocks)| (acc.0, *blocks)));
+        inner.finish()
+    }
+
     /// Verifies if the provided tag matches the computed Poly1305 tag.
     /// This perform `O(1)` comparasion of two tags
+    ///
+    /// With the `variable-time-verify` feature the tags are compared with a plain `==` instead,
+    /// which leaks through timing how many leading bytes match. **Never enable it outside of
+    /// profiling and benchmarks.**
     pub fn verify(self, tag: &Tag) -> bool {
-        let mut res: u8 = 1;
+        #[cfg(feature = "variable-time-verify")]
+        return self.finalize() == *tag;
+
+        #[cfg(not(feature = "variable-time-verify"))]
+        verify_tag(&self.finalize(), tag)
+    }
 
-        for (a, b) in self.finalize().into_iter().zip(tag) {
-            // perform constant time comparation
+    /// Verifies a truncated tag, comparing in constant time only its bytes against the leading
+    /// bytes of the computed tag. Returns false for an empty tag or one longer than 16 bytes.
+    ///
+    /// # Security
+    ///
+    /// Truncating the tag weakens the authentication: a forgery succeeds with a probability of
+    /// about `2^-(8 * tag.len())` per attempt, so an 8 bytes tag can be forged after around 2^64
+    /// attempts instead of 2^128, or far fewer if the receiver lets an attacker retry without
+    /// limits. Only use it where the protocol mandates truncated tags.
+    pub fn verify_truncated(self, tag: &[u8]) -> bool {
+        let expected = self.finalize();
+
+        if tag.is_empty() || tag.len() > expected.len() {
+            return false;
+        }
 
-            black_box({
-                // x will be 0 when a is equal b
-                let x = a ^ b;
+        ct_eq(&expected[..tag.len()], tag)
+    }
 
-                // if they are equal, then x and -x will be the same as 0 and -0
-                // otherwise x | -x with output a number with the msb set to 1
-                // then just need to shift that bit back into the first position
-                let y = (x | x.wrapping_neg()) >> 7;
+    #[cfg(feature = "subtle")]
+    /// Verifies the provided tag like `verify`, but returns the result as a `subtle::Choice` so it
+    /// can be combined with other checks without branching on it.
+    pub fn verify_ct(self, tag: &Tag) -> subtle::Choice {
+        use subtle::ConstantTimeEq as _;
 
-                // now if the lsb is 1, the two number is not equal and vice versa.
-                // to get the result, just need to flip it back
-                // and do operation AND to the current state
-                res &= y ^ 1;
-            })
-        }
+        self.finalize().ct_eq(tag)
+    }
+}
+
+#[cfg(feature = "std")]
+/// Feeds the written bytes to `update`, so a reader can be authenticated with `std::io::copy`.
+impl std::io::Write for Poly1305 {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+        self.update_vectored(bufs);
+        Ok(bufs.iter().map(|buf| buf.len()).sum())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns a copy of the key with its `r` half clamped as described in RFC 8439, the `s` half
+/// (bytes 16 to 31) is left untouched.
+///
+/// Clamping clears the top 4 bits of bytes 3, 7, 11 and 15 and the bottom 2 bits of bytes 4, 8
+/// and 12, which is `r &= 0x0ffffffc0ffffffc0ffffffc0fffffff` on the little-endian `r`.
+/// `Poly1305::new` clamps the key itself, so clamping beforehand does not change the tag.
+pub fn clamp(key: &Key) -> Key {
+    let mut clamped = *key;
+
+    for i in [3, 7, 11, 15] {
+        clamped[i] &= 0x0f;
+    }
+
+    for i in [4, 8, 12] {
+        clamped[i] &= 0xfc;
+    }
+
+    clamped
+}
+
+/// Compares two tags in constant time, for tags obtained without a Poly1305 state
+/// (e.g. parsed off the wire).
+pub fn verify_tag(a: &Tag, b: &Tag) -> bool {
+    ct_eq(a, b)
+}
+
+/// Compares two byte slices of the same length in constant time.
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    debug_assert_eq!(a.len(), b.len());
+    let mut res: u8 = 1;
+
+    for (a, b) in a.iter().zip(b) {
+        // perform constant time comparation
+
+        res &= black_box({
+            // x will be 0 when a is equal b
+            let x = a ^ b;
+
+            // if they are equal, then x and -x will be the same as 0 and -0
+            // otherwise x | -x with output a number with the msb set to 1
+            // then just need to shift that bit back into the first position
+            let y = (x | x.wrapping_neg()) >> 7;
+
+            // now if the lsb is 1, the two number is not equal and vice versa.
+            // to get the result, just need to flip it back
+            // and do operation AND to the current state
+            y ^ 1
+        });
+    }
+
+    res == 1
+}
+
+/// Poly1305 driven through the `core::hash::Hasher` trait, `finish` returns the first 8 bytes of
+/// the tag as a little-endian `u64`.
+///
+/// This is a keyed hash meant for integrity checks. Poly1305 keys must never be reused for two
+/// messages, so it must not be plugged into a `HashMap` as is, where the same key would hash
+/// every entry and attacker-controlled inputs could be used to recover it.
+#[derive(Clone)]
+pub struct Poly1305Hasher(Poly1305);
+
+impl Poly1305Hasher {
+    /// Creates a new Poly1305Hasher instance with the provided key.
+    pub fn new(key: &Key) -> Self {
+        Self(Poly1305::new(key))
+    }
+}
+
+impl core::hash::Hasher for Poly1305Hasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
 
-        res == 1
+    fn finish(&self) -> u64 {
+        let tag = self.0.clone().finalize();
+        // Unwrap here is safe because the tag is longer than 8 bytes
+        u64::from_le_bytes(tag[..8].try_into().unwrap())
     }
 }
 
@@ -154,6 +497,201 @@ mod test {
         assert!(mac.verify(&expected));
     }
 
+    #[test]
+    fn rfc_8439_example_vector_oneshot() {
+        let data = b"Cryptographic Forum Research Group";
+        let key: Key = [
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+            0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+            0x41, 0x49, 0xf5, 0x1b,
+        ];
+
+        let expected: Tag = [
+            0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6, 0xc2, 0x2b, 0x8b, 0xaf, 0x0c, 0x01,
+            0x27, 0xa9,
+        ];
+
+        assert_eq!(Poly1305::compute(&key, data), expected);
+        assert!(Poly1305::verify_oneshot(&key, data, &expected));
+        assert!(!Poly1305::verify_oneshot(&key, &data[1..], &expected));
+    }
+
+    #[test]
+    fn verify_truncated() {
+        let data = b"Cryptographic Forum Research Group";
+        let key: Key = [
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+            0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+            0x41, 0x49, 0xf5, 0x1b,
+        ];
+
+        let expected: Tag = [
+            0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6, 0xc2, 0x2b, 0x8b, 0xaf, 0x0c, 0x01,
+            0x27, 0xa9,
+        ];
+
+        let mac = Poly1305::new(&key).chain_update(data);
+        for len in [8, 12, 16] {
+            assert!(mac.clone().verify_truncated(&expected[..len]));
+
+            let mut forged = expected;
+            forged[len - 1] ^= 0x01;
+            assert!(!mac.clone().verify_truncated(&forged[..len]));
+        }
+
+        // Test vector #1 of RFC 8439, section A.3, has an all zero tag
+        let zero = Poly1305::new(&[0; 32]).chain_update(&[0; 64]);
+        assert!(zero.clone().verify_truncated(&[0; 8]));
+        assert!(zero.clone().verify_truncated(&[0; 12]));
+
+        assert!(!mac.clone().verify_truncated(&[]));
+        assert!(!mac.verify_truncated(&[0; 17]));
+    }
+
+    #[test]
+    fn rfc_8439_test_1_oneshot() {
+        let key: Key = Default::default();
+        let data = [0u8; 16 * 4];
+        let expected: Tag = Default::default();
+
+        assert_eq!(Poly1305::compute(&key, &data), expected);
+        assert!(Poly1305::verify_oneshot(&key, &data, &expected));
+    }
+
+    #[test]
+    fn rfc_8439_example_vector_from_r_s() {
+        let data = b"Cryptographic Forum Research Group";
+        let r = [
+            0x85, 0xd6, 0xbe, 0x08, 0x54, 0x55, 0x6d, 0x03, 0x7c, 0x44, 0x52, 0x0e, 0x40, 0xd5,
+            0x06, 0x08,
+        ];
+        let s = [
+            0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf, 0x41, 0x49,
+            0xf5, 0x1b,
+        ];
+
+        let expected: Tag = [
+            0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6, 0xc2, 0x2b, 0x8b, 0xaf, 0x0c, 0x01,
+            0x27, 0xa9,
+        ];
+
+        let mut mac = Poly1305::from_r_s(&r, &s);
+        mac.update(data);
+
+        assert_eq!(mac.finalize(), expected);
+    }
+
+    #[test]
+    fn try_update_internal_state() {
+        let key: Key = [0x42; 32];
+        let data = b"Cryptographic Forum Research Group";
+
+        let mut expected = Poly1305::new(&key);
+        expected.update(data);
+
+        let mut mac = Poly1305::new(&key);
+        assert!(mac.try_update(&data[..20]).is_ok());
+        assert!(mac.try_update(&data[20..]).is_ok());
+        assert_eq!(mac.finalize(), expected.finalize());
+
+        let mut mac = Poly1305::new(&key);
+        mac.leftover = BLOCK_SIZE + 1;
+        assert_eq!(mac.try_update(data).unwrap_err(), Error::InternalState);
+    }
+
+    #[test]
+    fn update_iovec() {
+        let key: Key = [0x42; 32];
+        let data = b"Cryptographic Forum Research Group";
+
+        let mut expected = Poly1305::new(&key);
+        expected.update(data);
+
+        let mut mac = Poly1305::new(&key);
+        mac.update_iovec(&[&data[..5], &data[5..21], &data[21..]]);
+
+        assert_eq!(mac.finalize(), expected.finalize());
+    }
+
+    #[test]
+    fn reset_reuses_instance() {
+        let key1: Key = [0x42; 32];
+        let key2: Key = [0x24; 32];
+        let data = b"Cryptographic Forum Research Group";
+
+        let mut mac = Poly1305::new(&key1);
+        mac.update(b"discarded data");
+        mac.reset(&key2);
+        mac.update(data);
+        assert_eq!(mac.finalize(), Poly1305::compute(&key2, data));
+
+        let mut mac = Poly1305::new(&key1);
+        mac.update(data);
+        assert_eq!(mac.finalize_reset(&key2), Poly1305::compute(&key1, data));
+        mac.update(&data[3..]);
+        assert_eq!(mac.finalize(), Poly1305::compute(&key2, &data[3..]));
+    }
+
+    #[test]
+    fn finalize_reset_pads_partial_block() {
+        let key1: Key = [0x61; 32];
+        let key2: Key = [0x16; 32];
+        let data: [u8; 40] = core::array::from_fn(|i| 0xc0 ^ i as u8);
+
+        // Messages ending on and off a block boundary, and an empty one
+        for (first, second) in [(0, 16), (1, 15), (15, 17), (16, 33), (17, 0), (33, 40)] {
+            let mut mac = Poly1305::new(&key1);
+            mac.update(&data[..first]);
+            assert_eq!(
+                mac.finalize_reset(&key2),
+                Poly1305::compute(&key1, &data[..first])
+            );
+
+            mac.update(&data[..second]);
+            assert_eq!(
+                mac.finalize_reset(&key1),
+                Poly1305::compute(&key2, &data[..second])
+            );
+        }
+    }
+
+    #[test]
+    fn merge_segments() {
+        let key: Key = [
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+            0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+            0x41, 0x49, 0xf5, 0x1b,
+        ];
+        let mut data = [0u8; 100];
+        data.iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = (i * 37 + 11) as u8);
+
+        let partial = |segment: &[u8]| {
+            let mut mac = Poly1305::new(&key);
+            mac.update(segment);
+            (
+                mac.into_partial(),
+                segment.len().div_ceil(BLOCK_SIZE) as u64,
+            )
+        };
+
+        let expected = Poly1305::compute(&key, &data);
+
+        let segments = [partial(&data[..48]), partial(&data[48..])];
+        assert_eq!(Poly1305::merge_segments(&key, &segments), expected);
+
+        let segments = [
+            partial(&data[..16]),
+            partial(&data[16..80]),
+            partial(&data[80..]),
+        ];
+        assert_eq!(Poly1305::merge_segments(&key, &segments), expected);
+
+        let segments = [partial(&data[..96]), partial(&data[96..])];
+        assert_eq!(Poly1305::merge_segments(&key, &segments), expected);
+    }
+
     #[test]
     fn rfc_8439_test_1() {
         let key: Key = Default::default();
@@ -423,4 +961,256 @@ mod test {
 
         assert!(mac.verify(&expected));
     }
+
+    #[test]
+    fn hasher_finish_is_truncated_tag() {
+        use core::hash::Hasher as _;
+
+        let key: Key = core::array::from_fn(|i| i as u8);
+        let data = b"Cryptographic Forum Research Group";
+
+        let mut hasher = Poly1305Hasher::new(&key);
+        hasher.write(&data[..10]);
+        hasher.write(&data[10..]);
+
+        let tag = Poly1305::compute(&key, data);
+        assert_eq!(hasher.finish().to_le_bytes(), tag[..8]);
+        // finish does not consume the state
+        assert_eq!(hasher.finish().to_le_bytes(), tag[..8]);
+    }
+
+    #[test]
+    fn clone_mid_computation_is_independent() {
+        let key: Key = core::array::from_fn(|i| (i * 3) as u8);
+        let head = [0x5a; 20];
+
+        let mut mac = Poly1305::new(&key);
+        mac.update(&head);
+        assert_eq!(mac.leftover, 4);
+
+        let mut other = mac.clone();
+        mac.update(b"first tail");
+        other.update(b"the second, longer tail");
+
+        let mut first = [0x5a; 30];
+        first[20..].copy_from_slice(b"first tail");
+        let mut second = [0x5a; 43];
+        second[20..].copy_from_slice(b"the second, longer tail");
+
+        assert_eq!(mac.finalize(), Poly1305::compute(&key, &first));
+        assert_eq!(other.finalize(), Poly1305::compute(&key, &second));
+    }
+
+    /// Coarse guard against catastrophic slowdowns, e.g. a refactor disabling inlining. The bound
+    /// is generous enough for unoptimized builds, run with `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn throughput_guard() {
+        extern crate std;
+
+        const ROUNDS: usize = 64;
+        static DATA: [u8; 1 << 20] = [0xa5; 1 << 20];
+
+        let key: Key = [0x42; 32];
+        let start = std::time::Instant::now();
+
+        let mut mac = Poly1305::new(&key);
+        for _ in 0..ROUNDS {
+            mac.update(black_box(&DATA));
+        }
+        black_box(mac.finalize());
+
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed < std::time::Duration::from_secs(30),
+            "MACing {ROUNDS} MiB took {elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn verify_tag_compares_every_bit() {
+        let tag: Tag = core::array::from_fn(|i| (i * 17) as u8);
+        assert!(verify_tag(&tag, &tag));
+
+        for bit in 0..128 {
+            let mut flipped = tag;
+            flipped[bit / 8] ^= 1 << (bit % 8);
+            assert!(!verify_tag(&tag, &flipped));
+        }
+    }
+
+    #[cfg(feature = "subtle")]
+    #[test]
+    fn verify_ct() {
+        let key: Key = [0x42; 32];
+        let data = b"constant time verification";
+
+        let mut mac = Poly1305::new(&key);
+        mac.update(data);
+        let mut tag = mac.clone().finalize();
+
+        assert!(bool::from(mac.clone().verify_ct(&tag)));
+        tag[15] ^= 0x80;
+        assert!(!bool::from(mac.verify_ct(&tag)));
+    }
+
+    #[test]
+    fn chain_update_keeps_leftover() {
+        let key: Key = [0x37; 32];
+        let data: [u8; 61] = core::array::from_fn(|i| i as u8);
+
+        let tag = Poly1305::new(&key)
+            .chain_update(&data[..3])
+            .chain_update(&data[3..20])
+            .chain_update(&[])
+            .chain_update(&data[20..])
+            .finalize();
+
+        assert_eq!(tag, Poly1305::compute(&key, &data));
+    }
+
+    #[test]
+    fn clamp_is_idempotent() {
+        let key: Key = [0xff; 32];
+        let clamped = clamp(&key);
+
+        assert_eq!(
+            u128::from_le_bytes(clamped[..16].try_into().unwrap()),
+            0x0ffffffc0ffffffc0ffffffc0fffffff
+        );
+        assert_eq!(clamped[16..], key[16..]);
+        assert_eq!(clamp(&clamped), clamped);
+        assert_eq!(
+            Poly1305::compute(&clamped, b"clamped"),
+            Poly1305::compute(&key, b"clamped")
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_write_matches_update() {
+        use std::io::Write as _;
+        use std::vec::Vec;
+
+        let key: Key = [0x4c; 32];
+        let data: Vec<u8> = (0..100_003).map(|i| (i * 7) as u8).collect();
+
+        let mut mac = Poly1305::new(&key);
+        for chunk in data.chunks(13) {
+            mac.write_all(chunk).unwrap();
+        }
+        mac.flush().unwrap();
+        assert_eq!(mac.finalize(), Poly1305::compute(&key, &data));
+
+        let mut mac = Poly1305::new(&key);
+        std::io::copy(&mut data.as_slice(), &mut mac).unwrap();
+        assert!(mac.verify(&Poly1305::compute(&key, &data)));
+    }
+
+    #[test]
+    fn update_interleaved() {
+        let key: Key = [0x4e; 32];
+        let a = [0xa0, 0xa1, 0xa2, 0xa3, 0xa4];
+        let b: [u8; 12] = core::array::from_fn(|i| 0xb0 + i as u8);
+
+        let expected = Poly1305::new(&key)
+            .chain_update(&a[..3])
+            .chain_update(&b[..3])
+            .chain_update(&a[3..])
+            .chain_update(&b[3..6])
+            .chain_update(&b[6..9])
+            .chain_update(&b[9..])
+            .finalize();
+
+        let mut mac = Poly1305::new(&key);
+        mac.update_interleaved(&mut [a.iter().copied(), b.iter().copied()], 3);
+        assert_eq!(mac.finalize(), expected);
+
+        // More bytes per round than a block
+        let mut mac = Poly1305::new(&key);
+        mac.update_interleaved(&mut [b.iter().copied(), a.iter().copied()], 40);
+        assert_eq!(
+            mac.finalize(),
+            Poly1305::new(&key)
+                .chain_update(&b)
+                .chain_update(&a)
+                .finalize()
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn update_vectored_across_block_boundary() {
+        use std::io::{IoSlice, Write as _};
+
+        let key: Key = [0x4d; 32];
+        let header = [0x11; 10];
+        let body = [0x22; 30];
+
+        let mut concatenated = [0; 40];
+        concatenated[..10].copy_from_slice(&header);
+        concatenated[10..].copy_from_slice(&body);
+        let expected = Poly1305::compute(&key, &concatenated);
+
+        // The first block is split between the header and the body
+        let mut mac = Poly1305::new(&key);
+        mac.update_vectored(&[IoSlice::new(&header), IoSlice::new(&body)]);
+        assert_eq!(mac.finalize(), expected);
+
+        let mut mac = Poly1305::new(&key);
+        let written = mac
+            .write_vectored(&[
+                IoSlice::new(&header),
+                IoSlice::new(&[]),
+                IoSlice::new(&body),
+            ])
+            .unwrap();
+        assert_eq!(written, 40);
+        assert_eq!(mac.finalize(), expected);
+    }
+
+    #[test]
+    fn const_constructor() {
+        const KEY: Key = [0x5b; 32];
+        const MAC: Poly1305 = Poly1305::new(&KEY);
+
+        let mut mac = MAC;
+        mac.update(b"known key");
+        assert_eq!(mac.finalize(), Poly1305::compute(&KEY, b"known key"));
+    }
+
+    #[test]
+    fn export_import_state() {
+        let key: Key = [0x6e; 32];
+        let data: [u8; 75] = core::array::from_fn(|i| i as u8 ^ 0xa5);
+
+        for split in [0, 7, 16, 40, 75] {
+            let mut mac = Poly1305::new(&key);
+            mac.update(&data[..split]);
+            let state = mac.export_state();
+
+            let mut resumed = Poly1305::import_state_slice(&key, &state).unwrap();
+            resumed.update(&data[split..]);
+            assert_eq!(resumed.finalize(), Poly1305::compute(&key, &data));
+        }
+
+        let state = Poly1305::new(&key).chain_update(&data[..20]).export_state();
+        assert!(Poly1305::import_state(&key, &state).is_ok());
+
+        for len in [0, EXPORTED_STATE_SIZE - 1, EXPORTED_STATE_SIZE + 1] {
+            let mut bytes = [0; EXPORTED_STATE_SIZE + 1];
+            bytes[..EXPORTED_STATE_SIZE].copy_from_slice(&state);
+            assert_eq!(
+                Poly1305::import_state_slice(&key, &bytes[..len]).err(),
+                Some(Error::InvalidLength)
+            );
+        }
+
+        let mut corrupted = state;
+        corrupted[40] = 16;
+        assert_eq!(
+            Poly1305::import_state(&key, &corrupted).err(),
+            Some(Error::InternalState)
+        );
+    }
 }
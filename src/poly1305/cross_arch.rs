@@ -17,9 +17,9 @@ impl Drop for Poly1305Inner {
 }
 
 impl Poly1305Inner {
-    pub(crate) fn new(key: &Key) -> Self {
-        let r_u64_1 = u64::from_le_bytes(key[0..8].try_into().unwrap());
-        let r_u64_2 = u64::from_le_bytes(key[8..16].try_into().unwrap());
+    pub(crate) const fn new(key: &Key) -> Self {
+        let r_u64_1 = u64_from_le(key, 0);
+        let r_u64_2 = u64_from_le(key, 8);
 
         let r = [
             r_u64_1 & 0xffc0fffffff,
@@ -27,14 +27,30 @@ impl Poly1305Inner {
             (r_u64_2 >> 24) & 0x00ffffffc0f,
         ];
 
-        let state = [
-            u64::from_le_bytes(key[16..24].try_into().unwrap()),
-            u64::from_le_bytes(key[24..32].try_into().unwrap()),
+        let state = [u64_from_le(key, 16), u64_from_le(key, 24)];
+
+        Self {
+            r,
+            h: [0; 3],
+            state,
+        }
+    }
+
+    pub(crate) const fn from_r_s(r: &[u8; 16], s: &[u8; 16]) -> Self {
+        let r_u64_1 = u64_from_le(r, 0);
+        let r_u64_2 = u64_from_le(r, 8);
+
+        let r = [
+            r_u64_1 & 0xfffffffffff,
+            ((r_u64_1 >> 44) | (r_u64_2 << 20)) & 0xfffffffffff,
+            (r_u64_2 >> 24) & 0x0ffffffffff,
         ];
 
+        let state = [u64_from_le(s, 0), u64_from_le(s, 8)];
+
         Self {
             r,
-            h: Default::default(),
+            h: [0; 3],
             state,
         }
     }
@@ -44,17 +60,10 @@ impl Poly1305Inner {
     pub(crate) fn append_block(&mut self, block: &Block, is_final: bool) {
         let hibit = if is_final { 0 } else { 1u64 << 40 };
 
-        let r0 = self.r[0];
-        let r1 = self.r[1];
-        let r2 = self.r[2];
-
         let mut h0 = self.h[0];
         let mut h1 = self.h[1];
         let mut h2 = self.h[2];
 
-        let s1 = r1 * (5 << 2);
-        let s2 = r2 * (5 << 2);
-
         // h += m[i]
         let t0 = u64::from_le_bytes(block[0..8].try_into().unwrap());
         let t1 = u64::from_le_bytes(block[8..].try_into().unwrap());
@@ -63,33 +72,50 @@ impl Poly1305Inner {
         h1 += ((t0 >> 44) | (t1 << 20)) & 0xfffffffffff;
         h2 += ((t1 >> 24) & 0x3ffffffffff) | hibit;
 
-        /* h *= r */
-        let d0     = mul_u64(h0, r0) + mul_u64(h1, s2) + mul_u64(h2, s1);
-        let mut d1 = mul_u64(h0, r1) + mul_u64(h1, r0) + mul_u64(h2, s2);
-        let mut d2 = mul_u64(h0, r2) + mul_u64(h1, r1) + mul_u64(h2, r0);
+        self.h = mul_reduce([h0, h1, h2], self.r);
+    }
 
-		/* (partial) h %= p */
-        let mut c: u64;
+    #[inline]
+    pub(crate) fn accumulator(&self) -> [u64; 3] {
+        self.h
+    }
 
-        c = (d0 >> 44) as u64;
-        h0 = d0 as u64 & 0xfffffffffff;
-        d1 += u128::from(c);
+    /// Replaces the accumulator, the limbs must be partially reduced as after `append_block`
+    pub(crate) fn set_accumulator(&mut self, h: [u64; 3]) {
+        self.h = h;
+    }
 
-        c = (d1 >> 44) as u64;
-        h1 = d1 as u64 & 0xfffffffffff;
-        d2 += u128::from(c);
+    /// Replaces the accumulator with the combination of the accumulators of consecutive message
+    /// segments, each one given with the number of blocks it covers.
+    ///
+    /// Since `h = (h + m) * r` for every block, the accumulator of the whole message is obtained
+    /// by evaluating `h = h * r^n + h_segment` for each segment of `n` blocks in order.
+    pub(crate) fn merge(&mut self, segments: impl Iterator<Item = ([u64; 3], u64)>) {
+        let mut h = [0; 3];
+
+        for (acc, blocks) in segments {
+            h = mul_reduce(h, self.r_pow(blocks));
+            h = carry([h[0] + acc[0], h[1] + acc[1], h[2] + acc[2]]);
+        }
 
-        c = (d2 >> 42) as u64;
-        h2 = d2 as u64 & 0x3ffffffffff;
-        h0 += c * 5;
+        self.h = h;
+    }
 
-        c = h0 >> 44;
-        h0 &= 0xfffffffffff;
-        h1 += c;
-        
-        self.h[0] = h0;
-        self.h[1] = h1;
-        self.h[2] = h2;
+    /// Computes `r^exp` with the square and multiply method
+    fn r_pow(&self, mut exp: u64) -> [u64; 3] {
+        let mut res = [1, 0, 0];
+        let mut base = self.r;
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                res = mul_reduce(res, base);
+            }
+
+            base = mul_reduce(base, base);
+            exp >>= 1;
+        }
+
+        res
     }
 
     #[inline]
@@ -173,7 +199,133 @@ impl Poly1305Inner {
     }
 }
 
+/// Computes `h * r` partially reduced modulo `2^130 - 5`
+#[rustfmt::skip]
+#[inline(always)]
+fn mul_reduce(h: [u64; 3], r: [u64; 3]) -> [u64; 3] {
+    let [r0, r1, r2] = r;
+    let [h0, h1, h2] = h;
+
+    let s1 = r1 * (5 << 2);
+    let s2 = r2 * (5 << 2);
+
+    /* h *= r */
+    let d0     = mul_u64(h0, r0) + mul_u64(h1, s2) + mul_u64(h2, s1);
+    let mut d1 = mul_u64(h0, r1) + mul_u64(h1, r0) + mul_u64(h2, s2);
+    let mut d2 = mul_u64(h0, r2) + mul_u64(h1, r1) + mul_u64(h2, r0);
+
+    /* (partial) h %= p */
+    let mut c: u64;
+
+    c = (d0 >> 44) as u64;
+    let mut h0 = d0 as u64 & 0xfffffffffff;
+    d1 += u128::from(c);
+
+    c = (d1 >> 44) as u64;
+    let mut h1 = d1 as u64 & 0xfffffffffff;
+    d2 += u128::from(c);
+
+    c = (d2 >> 42) as u64;
+    let h2 = d2 as u64 & 0x3ffffffffff;
+    h0 += c * 5;
+
+    c = h0 >> 44;
+    h0 &= 0xfffffffffff;
+    h1 += c;
+
+    [h0, h1, h2]
+}
+
+/// Propagates the carries of the limbs, partially reducing modulo `2^130 - 5`
+#[inline(always)]
+fn carry(h: [u64; 3]) -> [u64; 3] {
+    let [mut h0, mut h1, mut h2] = h;
+    let mut c: u64;
+
+    c = h0 >> 44;
+    h0 &= 0xfffffffffff;
+    h1 += c;
+
+    c = h1 >> 44;
+    h1 &= 0xfffffffffff;
+    h2 += c;
+
+    c = h2 >> 42;
+    h2 &= 0x3ffffffffff;
+    h0 += c * 5;
+
+    c = h0 >> 44;
+    h0 &= 0xfffffffffff;
+    h1 += c;
+
+    [h0, h1, h2]
+}
+
+/// Reads the little-endian `u64` starting at `at`, usable in const contexts
+#[inline(always)]
+const fn u64_from_le(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes([
+        bytes[at],
+        bytes[at + 1],
+        bytes[at + 2],
+        bytes[at + 3],
+        bytes[at + 4],
+        bytes[at + 5],
+        bytes[at + 6],
+        bytes[at + 7],
+    ])
+}
+
 #[inline(always)]
 fn mul_u64(a: u64, b: u64) -> u128 {
     u128::from(a) * u128::from(b)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limb_clamping_matches_rfc_mask() {
+        let keys: [Key; 3] = [
+            [0xff; 32],
+            core::array::from_fn(|i| (i as u8).wrapping_mul(0x9d) ^ 0x5a),
+            [
+                0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+                0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+                0x41, 0x49, 0xf5, 0x1b,
+            ],
+        ];
+
+        for key in keys {
+            let [r0, r1, r2] = Poly1305Inner::new(&key).r;
+            let from_limbs = u128::from(r0) | (u128::from(r1) << 44) | (u128::from(r2) << 88);
+
+            let r = u128::from_le_bytes(key[..16].try_into().unwrap());
+            let rfc_clamped = r & 0x0ffffffc0ffffffc0ffffffc0fffffff;
+
+            assert_eq!(from_limbs, rfc_clamped);
+            assert!(r0 < 1 << 44 && r1 < 1 << 44 && r2 < 1 << 40);
+        }
+    }
+
+    /// With `r = 1`, the tag of a single full block `m` is `m + s mod 2^128`, so any byte order
+    /// mistake in reading the key or the block or in writing the tag shows up on every target.
+    #[test]
+    fn byte_order_with_unit_r() {
+        let mut key: Key = [0; 32];
+        key[0] = 0x01;
+        key[16] = 0xff;
+        key[17] = 0x80;
+
+        let block: [u8; 16] = core::array::from_fn(|i| i as u8 + 1);
+        let mut inner = Poly1305Inner::new(&key);
+        inner.append_block(&block, false);
+
+        // The byte 0 overflows, 0x01 + 0xff, and carries into the byte 1, 0x02 + 0x80 + 1
+        let mut expected = block;
+        expected[0] = 0x00;
+        expected[1] = 0x83;
+        assert_eq!(inner.finish(), expected);
+    }
+}
@@ -0,0 +1,345 @@
+//! Wrappers wiping the key material from memory when it goes out of scope.
+//!
+//! The wrappers dereference to the underlying arrays, so they can be passed wherever a `&Key`
+//! or a `&Nonce` is expected:
+//!
+//! ```
+//! use chacha20poly1305::{secret::{SecretKey, SecretNonce}, ChaCha20};
+//!
+//! let key = SecretKey::from([0x42; 32]);
+//! let nonce = SecretNonce::from([0x24; 12]);
+//! let mut cipher = ChaCha20::new(&key, &nonce);
+//! ```
+
+use crate::chacha20::{Key, Nonce};
+use crate::error::Error;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// 256-bit key zeroized on drop, usable as a ChaCha20, ChaCha20-Poly1305 or Poly1305 key.
+#[derive(Clone)]
+pub struct SecretKey(Key);
+
+/// 96-bit nonce zeroized on drop.
+#[derive(Clone)]
+pub struct SecretNonce(Nonce);
+
+impl From<Key> for SecretKey {
+    #[inline]
+    fn from(key: Key) -> Self {
+        Self(key)
+    }
+}
+
+impl Deref for SecretKey {
+    type Target = Key;
+
+    #[inline]
+    fn deref(&self) -> &Key {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretKey {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Key {
+        &mut self.0
+    }
+}
+
+impl AsRef<Key> for SecretKey {
+    #[inline]
+    fn as_ref(&self) -> &Key {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for SecretKey {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Copies a key received as a slice, returns `Error::InvalidLength` unless it is exactly
+/// `size_of::<Key>()` bytes long.
+impl TryFrom<&[u8]> for SecretKey {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        bytes.try_into().map(Self).map_err(|_| Error::InvalidLength)
+    }
+}
+
+impl Zeroize for SecretKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SecretKey {}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+impl From<Nonce> for SecretNonce {
+    #[inline]
+    fn from(nonce: Nonce) -> Self {
+        Self(nonce)
+    }
+}
+
+impl Deref for SecretNonce {
+    type Target = Nonce;
+
+    #[inline]
+    fn deref(&self) -> &Nonce {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretNonce {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Nonce {
+        &mut self.0
+    }
+}
+
+impl AsRef<Nonce> for SecretNonce {
+    #[inline]
+    fn as_ref(&self) -> &Nonce {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for SecretNonce {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Copies a nonce received as a slice, returns `Error::InvalidLength` unless it is exactly
+/// `size_of::<Nonce>()` bytes long.
+impl TryFrom<&[u8]> for SecretNonce {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        bytes.try_into().map(Self).map_err(|_| Error::InvalidLength)
+    }
+}
+
+impl Zeroize for SecretNonce {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretNonce {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SecretNonce {}
+
+impl fmt::Debug for SecretNonce {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretNonce(..)")
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{Serialize, Serializer};
+
+    /// Visitor accepting exactly `N` bytes, either as a byte string or as a sequence
+    struct ArrayVisitor<const N: usize>;
+
+    impl<'de, const N: usize> Visitor<'de> for ArrayVisitor<N> {
+        type Value = [u8; N];
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{N} bytes")
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+            bytes
+                .try_into()
+                .map_err(|_| E::invalid_length(bytes.len(), &self))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut res = [0; N];
+
+            for i in 0..N {
+                match seq.next_element() {
+                    Ok(Some(val)) => res[i] = val,
+                    Ok(None) => {
+                        res.zeroize();
+                        return Err(de::Error::invalid_length(i, &self));
+                    }
+                    Err(err) => {
+                        res.zeroize();
+                        return Err(err);
+                    }
+                }
+            }
+
+            match seq.next_element::<de::IgnoredAny>() {
+                Ok(None) => {}
+                Ok(Some(_)) => {
+                    res.zeroize();
+                    return Err(de::Error::invalid_length(N + 1, &self));
+                }
+                Err(err) => {
+                    res.zeroize();
+                    return Err(err);
+                }
+            }
+
+            Ok(res)
+        }
+    }
+
+    impl Serialize for SecretKey {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SecretKey {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_bytes(ArrayVisitor).map(Self)
+        }
+    }
+
+    impl Serialize for SecretNonce {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SecretNonce {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_bytes(ArrayVisitor).map(Self)
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        extern crate std;
+
+        use super::*;
+        use std::string::ToString as _;
+
+        #[test]
+        fn serde_round_trip() {
+            let key = SecretKey::from(core::array::from_fn(|i| i as u8));
+            let nonce = SecretNonce::from(core::array::from_fn(|i| 0xa0 + i as u8));
+
+            let json = serde_json::to_string(&key).unwrap();
+            assert_eq!(*serde_json::from_str::<SecretKey>(&json).unwrap(), *key);
+            let json = serde_json::to_string(&nonce).unwrap();
+            assert_eq!(*serde_json::from_str::<SecretNonce>(&json).unwrap(), *nonce);
+
+            let bin = bincode::serialize(&key).unwrap();
+            assert_eq!(*bincode::deserialize::<SecretKey>(&bin).unwrap(), *key);
+            let bin = bincode::serialize(&nonce).unwrap();
+            assert_eq!(*bincode::deserialize::<SecretNonce>(&bin).unwrap(), *nonce);
+        }
+
+        #[test]
+        fn serde_rejects_wrong_length() {
+            let err = serde_json::from_str::<SecretNonce>("[1, 2, 3]").unwrap_err();
+            assert!(err.to_string().contains("invalid length 3"));
+            let err =
+                serde_json::from_str::<SecretNonce>("[0,0,0,0,0,0,0,0,0,0,0,0,0]").unwrap_err();
+            assert!(err.to_string().contains("invalid length 13"));
+
+            let bin = bincode::serialize(&[0u8; 31][..]).unwrap();
+            let err = bincode::deserialize::<SecretKey>(&bin).unwrap_err();
+            assert!(err.to_string().contains("invalid length 31"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ChaCha20, ChaCha20Poly1305};
+
+    #[test]
+    fn wrappers_work_in_place_of_arrays() -> crate::Result<()> {
+        let key: Key = [0x42; 32];
+        let nonce: Nonce = [0x24; 12];
+        let secret_key = SecretKey::from(key);
+        let secret_nonce = SecretNonce::from(nonce);
+
+        let mut data = *b"secret message";
+        let mut expected = data;
+        ChaCha20::new(&secret_key, &secret_nonce).perform_in_place(&mut data)?;
+        ChaCha20::new(&key, &nonce).perform_in_place(&mut expected)?;
+        assert_eq!(data, expected);
+
+        let tag = ChaCha20Poly1305::new(&secret_key).encrypt_in_place_detached(
+            &secret_nonce,
+            b"",
+            &mut data,
+        )?;
+        let expected_tag =
+            ChaCha20Poly1305::new(&key).encrypt_in_place_detached(&nonce, b"", &mut expected)?;
+        assert_eq!(tag, expected_tag);
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_from_slices() {
+        let bytes: [u8; 33] = core::array::from_fn(|i| i as u8);
+
+        let key = SecretKey::try_from(&bytes[..32]).unwrap();
+        assert_eq!(AsRef::<[u8]>::as_ref(&key), &bytes[..32]);
+        let nonce = SecretNonce::try_from(&bytes[..12]).unwrap();
+        assert_eq!(AsRef::<[u8]>::as_ref(&nonce), &bytes[..12]);
+
+        for len in [0, 31, 33] {
+            assert_eq!(
+                SecretKey::try_from(&bytes[..len]).unwrap_err(),
+                Error::InvalidLength
+            );
+        }
+        for len in [0, 11, 13] {
+            assert_eq!(
+                SecretNonce::try_from(&bytes[..len]).unwrap_err(),
+                Error::InvalidLength
+            );
+        }
+    }
+
+    #[test]
+    fn zeroize_wipes_the_material() {
+        let mut key = SecretKey::from([0x42; 32]);
+        let mut nonce = SecretNonce::from([0x24; 12]);
+
+        key.zeroize();
+        nonce.zeroize();
+
+        assert_eq!(*key, [0; 32]);
+        assert_eq!(*nonce, [0; 12]);
+    }
+}